@@ -0,0 +1,111 @@
+use std::time::Instant;
+
+use rand::prelude::*;
+
+use crate::evaluator::Evaluator;
+use crate::grid::Grid;
+use crate::moves::{self, MoveKind, MoveStats};
+use crate::schedule::Budget;
+use crate::telemetry::Sampler;
+
+/// One annealing run: the current state, the best state seen so far and the
+/// RNG driving its moves.
+#[derive(Clone)]
+pub struct Chain {
+    pub eval: Evaluator,
+    pub score: i64,
+    pub best: Grid,
+    pub best_score: i64,
+    pub rng: SmallRng,
+    pub stats: MoveStats,
+    pub iteration: usize,
+    /// Iteration of the last new best (or kick).
+    pub improved_at: usize,
+    pub kicks: usize,
+    sampler: Sampler,
+}
+
+impl Chain {
+    pub fn new(eval: Evaluator, seed: u64) -> Chain {
+        let score = eval.score();
+        Chain {
+            best: eval.grid().clone(),
+            best_score: score,
+            eval,
+            score,
+            rng: SmallRng::seed_from_u64(seed),
+            stats: MoveStats::default(),
+            iteration: 0,
+            improved_at: 0,
+            kicks: 0,
+            sampler: Sampler::new(),
+        }
+    }
+
+    /// Replaces the current state, e.g. after a replica exchange.
+    pub fn reset(&mut self, eval: Evaluator) {
+        self.score = eval.score();
+        self.eval = eval;
+        if self.score > self.best_score {
+            self.best_score = self.score;
+            self.best = self.eval.grid().clone();
+            self.improved_at = self.iteration;
+        }
+    }
+
+    /// Anneals until `budget` is used up; `temp` maps the progress in
+    /// `[0, 1)` through the budget to a temperature. Once `patience > 0`
+    /// steps pass without a new best, the best state is perturbed by `kick`
+    /// and the search resumes from the kicked state.
+    pub fn anneal_with_kicks<F, K>(
+        &mut self,
+        moves: &[MoveKind],
+        budget: Budget,
+        temp: F,
+        patience: usize,
+        mut kick: K,
+    ) where
+        F: Fn(f64) -> f64,
+        K: FnMut(&mut Evaluator, &mut SmallRng),
+    {
+        let from = Instant::now();
+        let first = self.iteration;
+        while let Some(ratio) = budget.progress(from, self.iteration - first) {
+            let now = Instant::now();
+            self.sampler
+                .sample(now, "sa", self.iteration, self.score, self.best_score);
+            self.step(moves, temp(ratio));
+            if patience > 0 && self.iteration - self.improved_at >= patience {
+                self.eval = Evaluator::from_grid(self.eval.dict.clone(), self.best.clone());
+                kick(&mut self.eval, &mut self.rng);
+                self.score = self.eval.score();
+                self.improved_at = self.iteration;
+                self.kicks += 1;
+            }
+        }
+    }
+
+    pub fn step(&mut self, moves: &[MoveKind], temp: f64) {
+        self.iteration += 1;
+        let kind = *moves.choose(&mut self.rng).unwrap();
+        let mut undo = match moves::apply_random(kind, &mut self.eval, &mut self.rng) {
+            Some(undo) => undo,
+            None => return,
+        };
+        let new_score = self.eval.score();
+        let diff = new_score as f64 - self.score as f64;
+        let prob = (diff / temp).exp();
+        let accepted = prob > self.rng.gen::<f64>();
+        if accepted {
+            if new_score > self.best_score {
+                self.best_score = new_score;
+                self.best = self.eval.grid().clone();
+                self.improved_at = self.iteration;
+            }
+            self.score = new_score;
+        } else {
+            self.eval.apply(&mut undo);
+        }
+        self.stats.record(kind, accepted);
+    }
+}
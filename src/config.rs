@@ -0,0 +1,393 @@
+use std::cell::RefCell;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::judge;
+use crate::moves::MoveKind;
+use crate::solver;
+use crate::telemetry::{self, Record};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParallelMode {
+    /// Neighbouring chains on the temperature ladder swap states.
+    Tempering,
+    /// Every chain restarts from the best state found by any chain.
+    AdoptBest,
+}
+
+impl ParallelMode {
+    /// The value that selects it on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            ParallelMode::Tempering => "tempering",
+            ParallelMode::AdoptBest => "adopt-best",
+        }
+    }
+}
+
+impl FromStr for ParallelMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ParallelMode, ()> {
+        match s {
+            "tempering" => Ok(ParallelMode::Tempering),
+            "adopt-best" => Ok(ParallelMode::AdoptBest),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowBuilder {
+    Greedy,
+    Beam,
+}
+
+impl RowBuilder {
+    /// The value that selects it on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            RowBuilder::Greedy => "greedy",
+            RowBuilder::Beam => "beam",
+        }
+    }
+}
+
+impl FromStr for RowBuilder {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<RowBuilder, ()> {
+        match s {
+            "greedy" => Ok(RowBuilder::Greedy),
+            "beam" => Ok(RowBuilder::Beam),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kick {
+    /// Rebuild a few rows from the patterns the rest of the grid misses.
+    Rows,
+    /// Fill a square of cells with random letters.
+    Block,
+}
+
+impl Kick {
+    /// The value that selects it on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Kick::Rows => "rows",
+            Kick::Block => "block",
+        }
+    }
+}
+
+impl FromStr for Kick {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Kick, ()> {
+        match s {
+            "rows" => Ok(Kick::Rows),
+            "block" => Ok(Kick::Block),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Knobs of the solver. Every field can be set with a `--flag value` argument
+/// or an `AHC_*` environment variable (the flag wins); the defaults are the
+/// values used for submission.
+#[derive(Debug, Clone)]
+pub struct SolverConfig {
+    /// Run `local_test` on generated inputs instead of reading stdin.
+    pub local: bool,
+    /// Print JSON lines about the run to stderr (see `telemetry`).
+    pub telemetry: bool,
+    /// Print the config, the result with its seed and the warm-start outcome
+    /// to stderr in plain text. Set whenever a flag or an `AHC_*` variable
+    /// is given, so that submission runs stay silent, unless `telemetry` is
+    /// on and reports them as JSON instead.
+    pub verbose: bool,
+    pub time_limit: Duration,
+    /// Share of the time limit the construction may take before it falls
+    /// back to cheaper rows; with 0.4 it builds every row in full on most
+    /// generated inputs.
+    pub construct_share: f64,
+    /// Time left unused before the time limit.
+    pub margin: Duration,
+    pub start_temp: f64,
+    pub end_temp: f64,
+    /// Number of letters ('A', 'B', ...) used to fill unused cells.
+    pub alphabet: u8,
+    /// Seeds the solver's RNG. A time-limited run still depends on how many
+    /// steps fit in the time, so only runs with `iterations` are repeatable.
+    pub seed: u64,
+    /// Stops every search after this many steps (moves tried by "sa" and
+    /// "tabu", children bred by "ga") instead of at the time limit, and never
+    /// cuts the construction or the dot phase short, so that the output only
+    /// depends on the input and `seed`.
+    pub iterations: Option<usize>,
+    pub moves: Vec<MoveKind>,
+    /// Worker threads used by `local_test`.
+    pub threads: usize,
+    /// Grid size and number of letters of the inputs `local_test` generates.
+    pub gen_size: usize,
+    pub gen_letters: usize,
+    /// Annealing chains run in parallel by `solve`; 1 runs a single chain
+    /// with the usual cooling schedule.
+    pub chains: usize,
+    pub parallel: ParallelMode,
+    /// Time between two synchronizations of the parallel chains.
+    pub exchange_interval: Duration,
+    pub row_builder: RowBuilder,
+    pub beam_width: usize,
+    /// Annealing steps without a new best after which a chain (each of the
+    /// parallel chains too) is kicked from its best state; 0 never kicks.
+    pub patience: usize,
+    pub kick: Kick,
+    /// Rows rebuilt by `Kick::Rows`, or side of the square of `Kick::Block`.
+    pub kick_size: usize,
+    /// Steps for which undoing a move of the tabu search stays tabu.
+    pub tabu_tenure: usize,
+    /// Moves the tabu search tries per step.
+    pub tabu_samples: usize,
+    /// Grids kept by the genetic algorithm.
+    pub population: usize,
+    /// Output file to continue from instead of constructing a grid; it is
+    /// overwritten when the new result scores higher.
+    pub warm_start: Option<String>,
+    /// Names of the solvers to run (see `solver::NAMES`); `main` uses the
+    /// first, `local_test` compares all of them.
+    pub solvers: Vec<String>,
+}
+
+impl Default for SolverConfig {
+    fn default() -> SolverConfig {
+        SolverConfig {
+            local: false,
+            telemetry: false,
+            verbose: false,
+            time_limit: Duration::from_secs_f64(2.9),
+            construct_share: 0.4,
+            margin: Duration::from_secs_f64(0.02),
+            start_temp: 100000.0,
+            end_temp: 10000.0,
+            alphabet: 8,
+            seed: 0,
+            iterations: None,
+            moves: MoveKind::ALL.to_vec(),
+            threads: 1,
+            gen_size: judge::DEFAULT_N,
+            gen_letters: judge::DEFAULT_K,
+            chains: 1,
+            parallel: ParallelMode::Tempering,
+            exchange_interval: Duration::from_secs_f64(0.05),
+            row_builder: RowBuilder::Greedy,
+            beam_width: 8,
+            patience: 0,
+            kick: Kick::Rows,
+            kick_size: 3,
+            solvers: vec!["sa".to_string()],
+            tabu_tenure: 30,
+            tabu_samples: 64,
+            population: 16,
+            warm_start: None,
+        }
+    }
+}
+
+impl SolverConfig {
+    /// A `config` record with one key per field, durations in seconds.
+    pub fn record(&self) -> Record {
+        let record = telemetry::record("config")
+            .bool("local", self.local)
+            .bool("telemetry", self.telemetry)
+            .bool("verbose", self.verbose)
+            .float("time_limit", self.time_limit.as_secs_f64())
+            .float("construct_share", self.construct_share)
+            .float("margin", self.margin.as_secs_f64())
+            .float("start_temp", self.start_temp)
+            .float("end_temp", self.end_temp)
+            .int("alphabet", self.alphabet as i64)
+            .int("seed", self.seed as i64);
+        let record = match self.iterations {
+            Some(iterations) => record.int("iterations", iterations as i64),
+            None => record.null("iterations"),
+        };
+        let record = record
+            .strs("moves", self.moves.iter().map(|m| m.name()))
+            .int("threads", self.threads as i64)
+            .int("gen_size", self.gen_size as i64)
+            .int("gen_letters", self.gen_letters as i64)
+            .int("chains", self.chains as i64)
+            .str("parallel", self.parallel.name())
+            .float("exchange_interval", self.exchange_interval.as_secs_f64())
+            .str("row_builder", self.row_builder.name())
+            .int("beam_width", self.beam_width as i64)
+            .int("patience", self.patience as i64)
+            .str("kick", self.kick.name())
+            .int("kick_size", self.kick_size as i64)
+            .int("tabu_tenure", self.tabu_tenure as i64)
+            .int("tabu_samples", self.tabu_samples as i64)
+            .int("population", self.population as i64);
+        let record = match &self.warm_start {
+            Some(path) => record.str("warm_start", path),
+            None => record.null("warm_start"),
+        };
+        record.strs("solvers", self.solvers.iter().map(|s| s.as_str()))
+    }
+
+    pub fn from_env() -> SolverConfig {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        // flags taking a value, to tell unknown arguments apart at the end
+        let flags = RefCell::new(Vec::new());
+        let lookup = |flag: &'static str, var: &str| -> Option<String> {
+            flags.borrow_mut().push(flag);
+            let arg = args
+                .iter()
+                .position(|a| a == flag)
+                .map(|i| args.get(i + 1).cloned().unwrap_or_default());
+            arg.or_else(|| std::env::var(var).ok())
+        };
+
+        let local = args.iter().any(|a| a == "--local")
+            || matches!(std::env::var("AHC_LOCAL").as_deref(), Ok("1") | Ok("true"));
+        let telemetry = args.iter().any(|a| a == "--telemetry")
+            || matches!(
+                std::env::var("AHC_TELEMETRY").as_deref(),
+                Ok("1") | Ok("true")
+            );
+        let verbose = !telemetry
+            && (!args.is_empty() || std::env::vars().any(|(var, _)| var.starts_with("AHC_")));
+        let mut config = SolverConfig {
+            local,
+            telemetry,
+            verbose,
+            ..SolverConfig::default()
+        };
+        if config.local {
+            config.time_limit = Duration::from_secs_f64(1.5);
+        }
+        if let Some(v) = lookup("--time-limit", "AHC_TIME_LIMIT") {
+            config.time_limit = Duration::from_secs_f64(parse("--time-limit", &v));
+        }
+        if let Some(v) = lookup("--construct-share", "AHC_CONSTRUCT_SHARE") {
+            config.construct_share = parse("--construct-share", &v);
+            assert!((0.0..=1.0).contains(&config.construct_share));
+        }
+        if let Some(v) = lookup("--margin", "AHC_MARGIN") {
+            config.margin = Duration::from_secs_f64(parse("--margin", &v));
+        }
+        if let Some(v) = lookup("--start-temp", "AHC_START_TEMP") {
+            config.start_temp = parse("--start-temp", &v);
+        }
+        if let Some(v) = lookup("--end-temp", "AHC_END_TEMP") {
+            config.end_temp = parse("--end-temp", &v);
+        }
+        if let Some(v) = lookup("--alphabet", "AHC_ALPHABET") {
+            config.alphabet = parse("--alphabet", &v);
+            assert!(1 <= config.alphabet && config.alphabet <= 8);
+        }
+        if let Some(v) = lookup("--seed", "AHC_SEED") {
+            config.seed = parse("--seed", &v);
+        }
+        if let Some(v) = lookup("--iterations", "AHC_ITERATIONS") {
+            config.iterations = Some(parse("--iterations", &v));
+            assert!(config.iterations >= Some(1));
+        }
+        if let Some(v) = lookup("--moves", "AHC_MOVES") {
+            config.moves = v
+                .split(',')
+                .map(|name| {
+                    MoveKind::from_name(name)
+                        .unwrap_or_else(|| panic!("unknown move for --moves: {}", name))
+                })
+                .collect();
+            assert!(!config.moves.is_empty());
+        }
+        if let Some(v) = lookup("--threads", "AHC_THREADS") {
+            config.threads = parse("--threads", &v);
+            assert!(config.threads >= 1);
+        }
+        if let Some(v) = lookup("--gen-size", "AHC_GEN_SIZE") {
+            config.gen_size = parse("--gen-size", &v);
+            assert!(config.gen_size >= 2);
+        }
+        if let Some(v) = lookup("--gen-letters", "AHC_GEN_LETTERS") {
+            config.gen_letters = parse("--gen-letters", &v);
+            assert!((1..=8).contains(&config.gen_letters));
+        }
+        if let Some(v) = lookup("--chains", "AHC_CHAINS") {
+            config.chains = parse("--chains", &v);
+            assert!(config.chains >= 1);
+        }
+        if let Some(v) = lookup("--parallel", "AHC_PARALLEL") {
+            config.parallel = parse("--parallel", &v);
+        }
+        if let Some(v) = lookup("--exchange-interval", "AHC_EXCHANGE_INTERVAL") {
+            config.exchange_interval = Duration::from_secs_f64(parse("--exchange-interval", &v));
+            assert!(config.exchange_interval > Duration::ZERO);
+        }
+        if let Some(v) = lookup("--row-builder", "AHC_ROW_BUILDER") {
+            config.row_builder = parse("--row-builder", &v);
+        }
+        if let Some(v) = lookup("--beam-width", "AHC_BEAM_WIDTH") {
+            config.beam_width = parse("--beam-width", &v);
+            assert!(config.beam_width >= 1);
+        }
+        if let Some(v) = lookup("--patience", "AHC_PATIENCE") {
+            config.patience = parse("--patience", &v);
+        }
+        if let Some(v) = lookup("--kick", "AHC_KICK") {
+            config.kick = parse("--kick", &v);
+        }
+        if let Some(v) = lookup("--kick-size", "AHC_KICK_SIZE") {
+            config.kick_size = parse("--kick-size", &v);
+            assert!(config.kick_size >= 1);
+        }
+        if let Some(v) = lookup("--tabu-tenure", "AHC_TABU_TENURE") {
+            config.tabu_tenure = parse("--tabu-tenure", &v);
+        }
+        if let Some(v) = lookup("--tabu-samples", "AHC_TABU_SAMPLES") {
+            config.tabu_samples = parse("--tabu-samples", &v);
+            assert!(config.tabu_samples >= 1);
+        }
+        if let Some(v) = lookup("--population", "AHC_POPULATION") {
+            config.population = parse("--population", &v);
+            assert!(config.population >= 2);
+        }
+        if let Some(v) = lookup("--warm-start", "AHC_WARM_START") {
+            assert!(!config.local, "--warm-start needs an input on stdin");
+            config.warm_start = Some(v);
+        }
+        if let Some(v) = lookup("--solver", "AHC_SOLVER") {
+            config.solvers = v.split(',').map(|name| name.to_string()).collect();
+            for name in &config.solvers {
+                if !solver::NAMES.contains(&name.as_str()) {
+                    panic!(
+                        "unknown solver for --solver: {} (one of {:?})",
+                        name,
+                        solver::NAMES
+                    );
+                }
+            }
+        }
+
+        let flags = flags.into_inner();
+        let mut rest = args.iter();
+        while let Some(arg) = rest.next() {
+            if flags.contains(&arg.as_str()) {
+                rest.next();
+            } else if arg != "--local" && arg != "--telemetry" {
+                panic!("unknown argument: {}", arg);
+            }
+        }
+        config
+    }
+}
+
+fn parse<T: FromStr>(flag: &str, v: &str) -> T {
+    v.parse()
+        .unwrap_or_else(|_| panic!("invalid value for {}: {:?}", flag, v))
+}
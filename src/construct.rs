@@ -0,0 +1,643 @@
+use std::cmp::Reverse;
+use std::ops::Neg;
+use std::time::{Duration, Instant};
+
+use rand::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::config::{RowBuilder, SolverConfig};
+use crate::judge::{Input, Output};
+use crate::overlap::OverlapTable;
+use crate::telemetry;
+
+pub fn find_maximum_prefix(row: &str, new: &str) -> usize {
+    for i in (0..=(row.len().min(new.len()))).rev() {
+        if row[row.len() - i..] == new[0..i] {
+            return i;
+        }
+    }
+    unreachable!();
+}
+
+/// `find_maximum_prefix(row, pattern_strs[next])` for a row ending with
+/// pattern `last`. Only a pattern containing `last` can overlap the row by
+/// more than `last` itself, so every other pair is a table lookup.
+fn row_overlap(
+    row: &str,
+    last: usize,
+    next: usize,
+    pattern_strs: &[String],
+    includes: &[FxHashSet<usize>],
+    overlap: &OverlapTable,
+) -> usize {
+    if includes[next].contains(&last) {
+        find_maximum_prefix(row, &pattern_strs[next])
+    } else {
+        overlap.get(last, next)
+    }
+}
+
+/// Whether `row + add` still fits on a row of `n` cells of the torus:
+/// anything past `n` characters wraps around onto the head of the row and
+/// must agree with it.
+fn fits_cycle(row: &str, add: &str, n: usize) -> bool {
+    let (row, add) = (row.as_bytes(), add.as_bytes());
+    let len = row.len() + add.len();
+    if len > 2 * n {
+        return false;
+    }
+    let at = |k: usize| {
+        if k < row.len() {
+            row[k]
+        } else {
+            add[k - row.len()]
+        }
+    };
+    (n.max(row.len())..len).all(|k| at(k) == at(k - n))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn greedy_row(
+    first: usize,
+    used: &FxHashSet<usize>,
+    pattern_strs: &[String],
+    weight: &[usize],
+    includes: &[FxHashSet<usize>],
+    include_weight: &[usize],
+    overlap: &OverlapTable,
+    n: usize,
+) -> (String, FxHashSet<usize>) {
+    let m = pattern_strs.len();
+
+    let mut row = String::new();
+    let mut local_used = FxHashSet::default();
+
+    row += &pattern_strs[first];
+    local_used.insert(first);
+    local_used.extend(includes[first].iter());
+    let mut last = first;
+
+    while let Some((next, com_len)) = (0..m)
+        .filter(|idx| !used.contains(idx) && !local_used.contains(idx))
+        .map(|idx| {
+            let com_len = row_overlap(&row, last, idx, pattern_strs, includes, overlap);
+            (idx, com_len)
+        })
+        .filter(|&(idx, com_len)| fits_cycle(&row, &pattern_strs[idx][com_len..], n))
+        .max_by_key(|&(idx, com_len)| {
+            (
+                if com_len == pattern_strs[idx].len() {
+                    1
+                } else {
+                    0
+                },
+                com_len,
+                ((pattern_strs[idx].len() - com_len) as i32).neg(),
+                weight[idx] + include_weight[idx],
+            )
+        })
+    {
+        row += &pattern_strs[next][com_len..];
+        local_used.insert(next);
+        local_used.extend(includes[next].iter());
+        last = next;
+    }
+
+    (row, local_used)
+}
+
+/// A row and the unused patterns occurring in it.
+pub type Row = (String, FxHashSet<usize>);
+
+/// Runs `greedy_row` from every unused maximal pattern, the ones covering
+/// the most weight first, and returns `(first, weight covered, row)` for each
+/// in that order. First patterns left when `until` passes are skipped (the
+/// first one is always tried); the flag tells whether any were.
+#[allow(clippy::too_many_arguments)]
+pub fn greedy_rows(
+    used: &FxHashSet<usize>,
+    pattern_strs: &[String],
+    weight: &[usize],
+    includes: &[FxHashSet<usize>],
+    include_weight: &[usize],
+    included_by: &[Vec<usize>],
+    overlap: &OverlapTable,
+    n: usize,
+    until: Option<Instant>,
+) -> (Vec<(usize, usize, Row)>, bool) {
+    let mut firsts: Vec<usize> = (0..pattern_strs.len())
+        .filter(|idx| !used.contains(idx))
+        .filter(|&idx| included_by[idx].is_empty())
+        .collect();
+    firsts.sort_by_key(|&idx| Reverse(weight[idx] + include_weight[idx]));
+    let mut rows = Vec::new();
+    for (k, &idx) in firsts.iter().enumerate() {
+        if k > 0 && matches!(until, Some(t) if Instant::now() >= t) {
+            return (rows, true);
+        }
+        let row = greedy_row(
+            idx,
+            used,
+            pattern_strs,
+            weight,
+            includes,
+            include_weight,
+            overlap,
+            n,
+        );
+        rows.push((idx, total_weight(&row.1, weight), row));
+    }
+    (rows, false)
+}
+
+/// Builds a row like `greedy_row`, but keeps the `width` best partial rows
+/// at every step instead of a single one. The partial rows start from the
+/// `width` first patterns whose greedy rows (see `greedy_rows`) cover the
+/// most weight, and are ranked by the weight of the patterns they cover
+/// (including the ones they `includes`), the shorter row first on ties. The
+/// row covering the most weight is returned, so it never covers less than
+/// the best greedy row. Past `until` the beam stops growing and the best
+/// row so far is returned. Also returns whether `greedy_rows` was cut short.
+#[allow(clippy::too_many_arguments)]
+pub fn beam_row(
+    used: &FxHashSet<usize>,
+    pattern_strs: &[String],
+    weight: &[usize],
+    includes: &[FxHashSet<usize>],
+    include_weight: &[usize],
+    included_by: &[Vec<usize>],
+    overlap: &OverlapTable,
+    n: usize,
+    width: usize,
+    until: Option<Instant>,
+) -> (Option<Row>, bool) {
+    let m = pattern_strs.len();
+
+    let (mut greedy, cut) = greedy_rows(
+        used,
+        pattern_strs,
+        weight,
+        includes,
+        include_weight,
+        included_by,
+        overlap,
+        n,
+        until,
+    );
+    // stable, so ties keep the order `greedy_rows` tried them in
+    greedy.sort_by_key(|&(_, covered, _)| Reverse(covered));
+    let rank = |row: &str, covered: usize| (Reverse(covered), row.len());
+    // (row, patterns covered by the row, their total weight, last pattern)
+    let mut best: Option<(String, FxHashSet<usize>, usize, usize)> = greedy
+        .first()
+        .map(|(idx, covered, (row, local_used))| (row.clone(), local_used.clone(), *covered, *idx));
+    let mut beam: Vec<(String, FxHashSet<usize>, usize, usize)> = greedy
+        .iter()
+        .take(width)
+        .map(|&(idx, _, _)| {
+            let mut local_used = FxHashSet::default();
+            local_used.insert(idx);
+            local_used.extend(includes[idx].iter());
+            let covered = total_weight(&local_used, weight);
+            (pattern_strs[idx].clone(), local_used, covered, idx)
+        })
+        .collect();
+
+    while !beam.is_empty() && !matches!(until, Some(t) if Instant::now() >= t) {
+        // (parent, next pattern, overlap, covered)
+        let mut candidates = Vec::new();
+        for (parent, (row, local_used, covered, last)) in beam.iter().enumerate() {
+            for idx in 0..m {
+                if used.contains(&idx) || local_used.contains(&idx) {
+                    continue;
+                }
+                let com_len = row_overlap(row, *last, idx, pattern_strs, includes, overlap);
+                if !fits_cycle(row, &pattern_strs[idx][com_len..], n) {
+                    continue;
+                }
+                let covered = covered
+                    + weight[idx]
+                    + includes[idx]
+                        .iter()
+                        .filter(|i| !local_used.contains(i))
+                        .map(|&i| weight[i])
+                        .sum::<usize>();
+                candidates.push((parent, idx, com_len, covered));
+            }
+        }
+        candidates.sort_by_key(|&(parent, idx, com_len, covered)| {
+            let row_len = beam[parent].0.len() + pattern_strs[idx].len() - com_len;
+            (Reverse(covered), row_len)
+        });
+
+        let mut next_beam: Vec<(String, FxHashSet<usize>, usize, usize)> = Vec::new();
+        let mut seen = FxHashSet::default();
+        for (parent, idx, com_len, covered) in candidates {
+            if next_beam.len() >= width {
+                break;
+            }
+            let (row, local_used, _, _) = &beam[parent];
+            let row = row.clone() + &pattern_strs[idx][com_len..];
+            if !seen.insert(row.clone()) {
+                continue;
+            }
+            let mut local_used = local_used.clone();
+            local_used.insert(idx);
+            local_used.extend(includes[idx].iter());
+            next_beam.push((row, local_used, covered, idx));
+        }
+
+        for state in &next_beam {
+            let (best_row, _, best_covered, _) = best.as_ref().unwrap();
+            if rank(&state.0, state.2) < rank(best_row, *best_covered) {
+                best = Some(state.clone());
+            }
+        }
+        beam = next_beam;
+    }
+
+    (best.map(|(row, local_used, _, _)| (row, local_used)), cut)
+}
+
+/// Lays `row` (possibly longer than `n`, see `fits_cycle`) on a cycle of
+/// `n` cells and fills the cells it leaves free. While some unused pattern
+/// fits over free cells consistently with the fixed ones, the one covering
+/// the most weight is written; this is where the tail and the head of the
+/// row get joined across the seam. Whatever is still free gets random
+/// letters. Returns the row and every unused pattern occurring in it,
+/// counting the occurrences across the seam.
+#[allow(clippy::too_many_arguments)]
+pub fn close_row<R: Rng>(
+    row: &str,
+    n: usize,
+    mut local_used: FxHashSet<usize>,
+    used: &FxHashSet<usize>,
+    pattern_strs: &[String],
+    weight: &[usize],
+    include_weight: &[usize],
+    alphabet: u8,
+    rng: &mut R,
+) -> (Vec<char>, FxHashSet<usize>) {
+    let mut cells: Vec<Option<u8>> = vec![None; n];
+    for (k, c) in row.bytes().enumerate() {
+        cells[k % n] = Some(c);
+    }
+
+    loop {
+        // (weight, -new cells, pattern, start)
+        let mut best: Option<(usize, i32, usize, usize)> = None;
+        for (idx, p) in pattern_strs.iter().enumerate() {
+            if used.contains(&idx) || local_used.contains(&idx) || p.len() > n {
+                continue;
+            }
+            for st in 0..n {
+                let mut fresh = 0;
+                let fits = p
+                    .bytes()
+                    .enumerate()
+                    .all(|(k, c)| match cells[(st + k) % n] {
+                        Some(x) => x == c,
+                        None => {
+                            fresh += 1;
+                            true
+                        }
+                    });
+                if !fits || fresh == 0 {
+                    continue;
+                }
+                let key = (weight[idx] + include_weight[idx], -fresh, idx, st);
+                if Some((key.0, key.1)) > best.map(|b| (b.0, b.1)) {
+                    best = Some(key);
+                }
+            }
+        }
+        let (_, _, idx, st) = match best {
+            Some(b) => b,
+            None => break,
+        };
+        for (k, c) in pattern_strs[idx].bytes().enumerate() {
+            cells[(st + k) % n] = Some(c);
+        }
+        local_used.insert(idx);
+    }
+
+    let row: Vec<u8> = cells
+        .into_iter()
+        .map(|c| c.unwrap_or_else(|| b'A' + rng.gen_range(0, alphabet)))
+        .collect();
+    for (idx, p) in pattern_strs.iter().enumerate() {
+        if used.contains(&idx) || local_used.contains(&idx) || p.len() > n {
+            continue;
+        }
+        if (0..n).any(|st| p.bytes().enumerate().all(|(k, c)| row[(st + k) % n] == c)) {
+            local_used.insert(idx);
+        }
+    }
+
+    (row.into_iter().map(|c| c as char).collect(), local_used)
+}
+
+/// `includes[j]` holds the patterns occurring in pattern `j` and
+/// `included_by[i]` the patterns pattern `i` occurs in; the patterns must be
+/// distinct.
+pub fn inclusions(pattern_strs: &[String]) -> (Vec<FxHashSet<usize>>, Vec<Vec<usize>>) {
+    let m = pattern_strs.len();
+    let mut includes: Vec<FxHashSet<usize>> = vec![FxHashSet::default(); m];
+    let mut included_by: Vec<Vec<usize>> = vec![Vec::new(); m];
+    for i in 0..m {
+        for j in 0..m {
+            if pattern_strs[j].len() > pattern_strs[i].len()
+                && pattern_strs[j].contains(&pattern_strs[i])
+            {
+                includes[j].insert(i);
+                included_by[i].push(j);
+            }
+        }
+    }
+    (includes, included_by)
+}
+
+pub fn total_weight(set: &FxHashSet<usize>, weight: &[usize]) -> usize {
+    set.iter().map(|&i| weight[i]).sum()
+}
+
+/// Builds the initial grid one row at a time, each row chaining as many
+/// still uncovered patterns as fit. A row may take the time left before
+/// `until` but what the rows after it need to try a single first pattern
+/// each, so rows run short of time only when the whole construction does,
+/// and then the last ones are cut first; the construction overruns `until`
+/// by at most a few cheap rows. Without `until` every row tries every first
+/// pattern.
+pub fn construct<R: Rng>(
+    input: &Input,
+    config: &SolverConfig,
+    rng: &mut R,
+    until: Option<Instant>,
+) -> Output {
+    let n = input.N;
+    let alphabet = config.alphabet.min(input.K as u8);
+
+    // every copy of a duplicated string is scored separately
+    let mut multiplicity: FxHashMap<String, usize> = FxHashMap::default();
+    for i in 0..input.M {
+        let s: String = input.s[i].iter().collect::<String>();
+        *multiplicity.entry(s).or_insert(0) += 1;
+    }
+
+    let mut pattern_strs: Vec<String> = multiplicity.keys().cloned().collect::<Vec<_>>();
+    pattern_strs.sort_by_key(|s| s.len());
+    let weight: Vec<usize> = pattern_strs.iter().map(|s| multiplicity[s]).collect();
+
+    let mut patterns: Vec<Vec<u8>> = Vec::new();
+    for s in &pattern_strs {
+        let s: Vec<char> = s.chars().collect();
+        let mut p = Vec::new();
+        for c in s {
+            p.push(c as u8 - b'A');
+        }
+        patterns.push(p);
+    }
+
+    let (mut includes, included_by) = inclusions(&pattern_strs);
+
+    let overlap = OverlapTable::new(&patterns);
+
+    let mut include_weight: Vec<usize> = includes
+        .iter()
+        .map(|inc| total_weight(inc, &weight))
+        .collect();
+
+    let mut used = FxHashSet::default();
+
+    // how long a row built from a single first pattern takes, at most: the
+    // first row's from its heaviest first pattern
+    let single = match until {
+        Some(_) => {
+            let start = Instant::now();
+            let heaviest = (0..pattern_strs.len())
+                .filter(|&idx| included_by[idx].is_empty())
+                .max_by_key(|&idx| weight[idx] + include_weight[idx]);
+            if let Some(first) = heaviest {
+                greedy_row(
+                    first,
+                    &used,
+                    &pattern_strs,
+                    &weight,
+                    &includes,
+                    &include_weight,
+                    &overlap,
+                    n,
+                );
+            }
+            start.elapsed()
+        }
+        None => Duration::ZERO,
+    };
+
+    let mut answer = Vec::new();
+    let mut coverage = Vec::new();
+    let mut cut_short = 0;
+    for r in 0..n {
+        // a row may take the time left but what the rows after it need to
+        // try a single first pattern each; past that the remaining first
+        // patterns (least weight first) are skipped
+        let row_until = until.map(|until| {
+            let now = Instant::now();
+            let reserve = single * (n - r - 1) as u32;
+            until.checked_sub(reserve).unwrap_or(now).max(now)
+        });
+        let (built, cut) = match config.row_builder {
+            RowBuilder::Beam => beam_row(
+                &used,
+                &pattern_strs,
+                &weight,
+                &includes,
+                &include_weight,
+                &included_by,
+                &overlap,
+                n,
+                config.beam_width,
+                row_until,
+            ),
+            RowBuilder::Greedy => {
+                let (rows, cut) = greedy_rows(
+                    &used,
+                    &pattern_strs,
+                    &weight,
+                    &includes,
+                    &include_weight,
+                    &included_by,
+                    &overlap,
+                    n,
+                    row_until,
+                );
+                // the first row covering the most weight
+                let best = rows
+                    .into_iter()
+                    .rev()
+                    .max_by_key(|&(_, covered, _)| covered)
+                    .map(|(_, _, row)| row);
+                (best, cut)
+            }
+        };
+        if cut {
+            cut_short += 1;
+        }
+        let (row, using) = built.unwrap_or_default();
+        let (row, using) = close_row(
+            &row,
+            n,
+            using,
+            &used,
+            &pattern_strs,
+            &weight,
+            &include_weight,
+            alphabet,
+            rng,
+        );
+        coverage.push(total_weight(&using, &weight));
+
+        for x in using {
+            used.insert(x);
+            for &i in &included_by[x] {
+                if includes[i].remove(&x) {
+                    include_weight[i] -= weight[x];
+                }
+            }
+        }
+
+        answer.push(row);
+    }
+    telemetry::record("construct")
+        .ints("coverage", coverage.iter().map(|&c| c as i64))
+        .int("cut_short", cut_short)
+        .emit();
+
+    answer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judge;
+
+    fn strs(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn beam_row_covers_at_least_the_best_greedy_row() {
+        let input = judge::gen(0);
+        let mut pattern_strs: Vec<String> = input
+            .s
+            .iter()
+            .map(|s| s.iter().collect::<String>())
+            .collect::<FxHashSet<_>>()
+            .into_iter()
+            .collect();
+        pattern_strs.sort();
+        let m = pattern_strs.len();
+        let weight = vec![1; m];
+        let (mut includes, included_by) = inclusions(&pattern_strs);
+        let overlap = OverlapTable::new(&pattern_strs);
+        let mut include_weight: Vec<usize> = includes.iter().map(|inc| inc.len()).collect();
+
+        let mut used = FxHashSet::default();
+        for _ in 0..3 {
+            let (rows, cut) = greedy_rows(
+                &used,
+                &pattern_strs,
+                &weight,
+                &includes,
+                &include_weight,
+                &included_by,
+                &overlap,
+                input.N,
+                None,
+            );
+            assert!(!cut);
+            let greedy = rows.iter().map(|&(_, covered, _)| covered).max().unwrap();
+            let (row, cut) = beam_row(
+                &used,
+                &pattern_strs,
+                &weight,
+                &includes,
+                &include_weight,
+                &included_by,
+                &overlap,
+                input.N,
+                4,
+                None,
+            );
+            assert!(!cut);
+            let (row, using) = row.unwrap();
+            assert!(fits_cycle("", &row, input.N));
+            for &idx in &using {
+                assert!(!used.contains(&idx));
+                assert!(row.contains(&pattern_strs[idx]));
+            }
+            assert!(using.len() >= greedy, "{} < {}", using.len(), greedy);
+            for x in using {
+                used.insert(x);
+                for &i in &included_by[x] {
+                    if includes[i].remove(&x) {
+                        include_weight[i] -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fits_cycle_checks_the_wrapped_tail() {
+        assert!(fits_cycle("ABC", "D", 4));
+        assert!(fits_cycle("ABCD", "AB", 4));
+        assert!(!fits_cycle("ABCD", "B", 4));
+        assert!(!fits_cycle("ABC", "DAC", 4));
+        // rows already longer than n
+        assert!(fits_cycle("ABCDAB", "C", 4));
+        assert!(!fits_cycle("ABCDAB", "D", 4));
+        assert!(!fits_cycle("ABCDABCD", "A", 4));
+    }
+
+    #[test]
+    fn close_row_counts_patterns_across_the_seam() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let pattern_strs = strs(&["ABC", "CXA", "AAB", "BB", "AB"]);
+        let m = pattern_strs.len();
+        let used: FxHashSet<usize> = vec![4].into_iter().collect();
+        let local_used: FxHashSet<usize> = vec![0].into_iter().collect();
+        let (row, local_used) = close_row(
+            "ABC",
+            5,
+            local_used,
+            &used,
+            &pattern_strs,
+            &[1, 2, 1, 1, 1],
+            &vec![0; m],
+            8,
+            &mut rng,
+        );
+        // "CXA" closes the row; "AAB" only occurs across the seam and "AB" is
+        // used by another row
+        assert_eq!(row.iter().collect::<String>(), "ABCXA");
+        let mut found: Vec<usize> = local_used.into_iter().collect();
+        found.sort_unstable();
+        assert_eq!(found, vec![0, 1, 2]);
+
+        // a row longer than n wraps onto its head
+        let (row, local_used) = close_row(
+            "ABCDA",
+            4,
+            FxHashSet::default(),
+            &FxHashSet::default(),
+            &strs(&["DAB", "CA"]),
+            &[1, 1],
+            &[0, 0],
+            8,
+            &mut rng,
+        );
+        assert_eq!(row.iter().collect::<String>(), "ABCD");
+        assert_eq!(local_used.into_iter().collect::<Vec<_>>(), vec![0]);
+    }
+}
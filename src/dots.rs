@@ -0,0 +1,126 @@
+use std::time::Instant;
+
+use rand::prelude::*;
+
+use crate::evaluator::Evaluator;
+use crate::grid::DOT;
+
+const TRIALS: usize = 100;
+
+/// Once every pattern is covered, keeps one occurrence per pattern and turns
+/// every cell none of the kept occurrences needs into '.'.
+///
+/// Each trial picks occurrences greedily (longest patterns first, then the
+/// occurrence adding the fewest new cells); the trial needing the fewest
+/// cells wins. Trials stop at `deadline`, if any.
+pub fn maximize_dots<R: Rng>(eval: &mut Evaluator, rng: &mut R, deadline: Option<Instant>) {
+    if !eval.is_complete() {
+        return;
+    }
+
+    let n = eval.grid().n();
+    let occ = eval.occurrences();
+    let patterns = &eval.dict.patterns;
+    let mut order: Vec<usize> = (0..patterns.len()).collect();
+
+    let mut best: Option<(usize, Vec<Vec<bool>>)> = None;
+    for _ in 0..TRIALS {
+        if matches!(deadline, Some(deadline) if Instant::now() >= deadline) {
+            break;
+        }
+
+        order.shuffle(rng);
+        order.sort_by_key(|&p| std::cmp::Reverse(patterns[p].len()));
+
+        let mut needed = vec![vec![false; n]; n];
+        let mut used = 0;
+        for &p in &order {
+            let new_cells = |&(i, j, d): &(usize, usize, usize)| {
+                (0..patterns[p].len().min(n))
+                    .filter(|&k| {
+                        let (ci, cj) = if d == 0 {
+                            (i, (j + k) % n)
+                        } else {
+                            ((i + k) % n, j)
+                        };
+                        !needed[ci][cj]
+                    })
+                    .count()
+            };
+            let &(i, j, d) = occ[p].iter().min_by_key(|o| new_cells(o)).unwrap();
+            for k in 0..patterns[p].len().min(n) {
+                let (ci, cj) = if d == 0 {
+                    (i, (j + k) % n)
+                } else {
+                    ((i + k) % n, j)
+                };
+                if !needed[ci][cj] {
+                    needed[ci][cj] = true;
+                    used += 1;
+                }
+            }
+        }
+
+        let better = match &best {
+            Some((b, _)) => used < *b,
+            None => true,
+        };
+        if better {
+            best = Some((used, needed));
+        }
+    }
+
+    if let Some((_, needed)) = best {
+        let mut writes = Vec::new();
+        for (i, row) in needed.iter().enumerate() {
+            for (j, &need) in row.iter().enumerate() {
+                if !need && eval.get(i, j) != DOT {
+                    writes.push((i, j, DOT));
+                }
+            }
+        }
+        eval.apply(&mut writes);
+        assert!(eval.is_complete());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judge::{self, Scorer};
+
+    #[test]
+    fn keeps_the_planted_answer_complete() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for seed in 0..5 {
+            let (input, answer) = judge::gen_with_answer(seed);
+            let scorer = Scorer::new(&input);
+            let mut eval = Evaluator::new(&input, &answer.a);
+            maximize_dots(&mut eval, &mut rng, None);
+            assert!(eval.is_complete());
+            assert!(eval.score() >= 100_000_000);
+            assert_eq!(
+                scorer.compute_score_detail(&eval.to_output()).0,
+                eval.score()
+            );
+        }
+    }
+
+    #[test]
+    fn dots_the_planted_answer_of_seed_1() {
+        // the planted answer of seed 1 has cells no pattern needs; on most
+        // seeds every cell of it is needed
+        let mut rng = SmallRng::seed_from_u64(0);
+        let (input, answer) = judge::gen_with_answer(1);
+        let mut eval = Evaluator::new(&input, &answer.a);
+        maximize_dots(&mut eval, &mut rng, None);
+        let dots = eval
+            .to_output()
+            .iter()
+            .flatten()
+            .filter(|&&c| c == '.')
+            .count();
+        assert!(dots > 0);
+        assert!(eval.score() > 100_000_000, "{}", eval.score());
+    }
+}
@@ -0,0 +1,432 @@
+use std::sync::Arc;
+
+use rustc_hash::FxHashMap;
+
+use crate::grid::{Grid, DOT};
+use crate::judge::{Input, Output};
+
+const NONE: u32 = u32::MAX;
+
+/// Distinct patterns of an input, stored in a trie so that every pattern
+/// starting at a given cell can be found in a single walk.
+pub struct Dictionary {
+    pub patterns: Vec<Vec<u8>>,
+    /// Number of times each distinct pattern appears in `Input::s`.
+    pub weight: Vec<usize>,
+    pub total: usize,
+    pub max_len: usize,
+    /// Letters of the input: cell values are `0..alphabet` or `DOT`.
+    pub alphabet: u8,
+    next: Vec<[u32; 8]>,
+    terminal: Vec<u32>,
+}
+
+impl Dictionary {
+    pub fn new(input: &Input) -> Dictionary {
+        let mut index: FxHashMap<&[char], usize> = FxHashMap::default();
+        let mut patterns: Vec<Vec<u8>> = Vec::new();
+        let mut weight = Vec::new();
+        for s in &input.s {
+            let id = *index.entry(&s[..]).or_insert_with(|| {
+                patterns.push(s.iter().map(|&c| c as u8 - b'A').collect());
+                weight.push(0);
+                patterns.len() - 1
+            });
+            weight[id] += 1;
+        }
+
+        let mut next = vec![[0u32; 8]];
+        let mut terminal = vec![NONE];
+        for (id, p) in patterns.iter().enumerate() {
+            let mut node = 0;
+            for &c in p {
+                if next[node][c as usize] == 0 {
+                    next[node][c as usize] = next.len() as u32;
+                    next.push([0; 8]);
+                    terminal.push(NONE);
+                }
+                node = next[node][c as usize] as usize;
+            }
+            terminal[node] = id as u32;
+        }
+
+        let max_len = patterns.iter().map(|p| p.len()).max().unwrap_or(0);
+        Dictionary {
+            patterns,
+            weight,
+            total: input.M,
+            max_len,
+            alphabet: input.K as u8,
+            next,
+            terminal,
+        }
+    }
+
+    /// Distinct patterns occurring in `line` (cell values) read as a cycle,
+    /// in increasing order.
+    pub fn occurring(&self, line: &[u8]) -> Vec<usize> {
+        let n = line.len();
+        let mut found = Vec::new();
+        for st in 0..n {
+            let mut node = 0;
+            for k in 0..self.max_len {
+                let c = line[(st + k) % n];
+                if c == DOT {
+                    break;
+                }
+                node = self.next[node][c as usize] as usize;
+                if node == 0 {
+                    break;
+                }
+                if self.terminal[node] != NONE {
+                    found.push(self.terminal[node] as usize);
+                }
+            }
+        }
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
+
+/// A single cell assignment `(i, j, value)`.
+pub type Write = (usize, usize, u8);
+
+/// Keeps the number of occurrences of every pattern in a grid and updates it
+/// incrementally, so a move only rescans the windows that touch changed cells.
+/// `score` always equals `compute_score_detail` of the current grid.
+#[derive(Clone)]
+pub struct Evaluator {
+    pub dict: Arc<Dictionary>,
+    grid: Grid,
+    count: Vec<u32>,
+    covered: usize,
+    /// Distinct patterns with no occurrence, in no particular order.
+    uncovered: Vec<u32>,
+    /// Index of each pattern in `uncovered`, or `NONE`.
+    slot: Vec<u32>,
+    dots: usize,
+    stamp: Vec<u32>,
+    current: u32,
+    starts: Vec<(usize, usize, usize)>,
+}
+
+impl Evaluator {
+    pub fn new(input: &Input, out: &Output) -> Evaluator {
+        Evaluator::with_dictionary(Arc::new(Dictionary::new(input)), out)
+    }
+
+    pub fn with_dictionary(dict: Arc<Dictionary>, out: &Output) -> Evaluator {
+        Evaluator::from_grid(dict, Grid::from(out))
+    }
+
+    pub fn from_grid(dict: Arc<Dictionary>, grid: Grid) -> Evaluator {
+        let n = grid.n();
+        let dots = (0..n)
+            .map(|i| (0..n).filter(|&j| grid.get(i, j) == DOT).count())
+            .sum();
+        let m = dict.patterns.len();
+        let mut eval = Evaluator {
+            count: vec![0; m],
+            dict,
+            grid,
+            covered: 0,
+            uncovered: (0..m as u32).collect(),
+            slot: (0..m as u32).collect(),
+            dots,
+            stamp: vec![0; n * n * 2],
+            current: 0,
+            starts: Vec::new(),
+        };
+        for i in 0..n {
+            for j in 0..n {
+                for d in 0..2 {
+                    eval.walk(i, j, d, true);
+                }
+            }
+        }
+        eval
+    }
+
+    pub fn score(&self) -> i64 {
+        let n = self.grid.n();
+        let score = if self.covered < self.dict.total {
+            1e8 * self.covered as f64 / self.dict.total as f64
+        } else {
+            1e8 * (2 * n * n) as f64 / (2 * n * n - self.dots) as f64
+        };
+        score.round() as i64
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> u8 {
+        self.grid.get(i, j)
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn is_covered(&self, p: usize) -> bool {
+        self.count[p] > 0
+    }
+
+    /// Ids of the distinct patterns that do not occur in the grid.
+    pub fn uncovered(&self) -> &[u32] {
+        &self.uncovered
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.covered == self.dict.total
+    }
+
+    /// Lists the `(i, j, d)` of every occurrence of each distinct pattern.
+    pub fn occurrences(&self) -> Vec<Vec<(usize, usize, usize)>> {
+        let n = self.grid.n();
+        let dict = &*self.dict;
+        let mut occ = vec![Vec::new(); dict.patterns.len()];
+        for i in 0..n {
+            for j in 0..n {
+                for d in 0..2 {
+                    let mut node = 0;
+                    for k in 0..dict.max_len {
+                        let c = if d == 0 {
+                            self.grid.get(i, (j + k) % n)
+                        } else {
+                            self.grid.get((i + k) % n, j)
+                        };
+                        if c == DOT {
+                            break;
+                        }
+                        node = dict.next[node][c as usize] as usize;
+                        if node == 0 {
+                            break;
+                        }
+                        if dict.terminal[node] != NONE {
+                            occ[dict.terminal[node] as usize].push((i, j, d));
+                        }
+                    }
+                }
+            }
+        }
+        occ
+    }
+
+    pub fn to_output(&self) -> Output {
+        Output::from(&self.grid)
+    }
+
+    /// Assigns every cell in `writes` (which must be pairwise distinct) and
+    /// leaves the previous values in `writes`, so applying it again reverts
+    /// the change.
+    pub fn apply(&mut self, writes: &mut [Write]) {
+        let n = self.grid.n();
+        self.current += 1;
+        self.starts.clear();
+        let reach = self.dict.max_len.min(n);
+        for &(i, j, _) in writes.iter() {
+            for k in 0..reach {
+                let h = (i, (j + n - k) % n, 0);
+                let v = ((i + n - k) % n, j, 1);
+                for &(si, sj, d) in &[h, v] {
+                    let id = (si * n + sj) * 2 + d;
+                    if self.stamp[id] != self.current {
+                        self.stamp[id] = self.current;
+                        self.starts.push((si, sj, d));
+                    }
+                }
+            }
+        }
+
+        for idx in 0..self.starts.len() {
+            let (i, j, d) = self.starts[idx];
+            self.walk(i, j, d, false);
+        }
+        for w in writes.iter_mut() {
+            let old = self.grid.get(w.0, w.1);
+            if old == DOT {
+                self.dots -= 1;
+            }
+            if w.2 == DOT {
+                self.dots += 1;
+            }
+            self.grid.set(w.0, w.1, w.2);
+            w.2 = old;
+        }
+        for idx in 0..self.starts.len() {
+            let (i, j, d) = self.starts[idx];
+            self.walk(i, j, d, true);
+        }
+    }
+
+    pub fn swap_rows(&mut self, a: usize, b: usize) -> Vec<Write> {
+        let n = self.grid.n();
+        let mut writes = Vec::with_capacity(2 * n);
+        if a != b {
+            for j in 0..n {
+                writes.push((a, j, self.grid.get(b, j)));
+                writes.push((b, j, self.grid.get(a, j)));
+            }
+        }
+        self.apply(&mut writes);
+        writes
+    }
+
+    /// Cyclically shifts row `i` left by `st`.
+    pub fn rotate_row(&mut self, i: usize, st: usize) -> Vec<Write> {
+        let n = self.grid.n();
+        let st = st % n;
+        let mut writes = Vec::with_capacity(n);
+        if st > 0 {
+            for j in 0..n {
+                writes.push((i, j, self.grid.get(i, (j + st) % n)));
+            }
+        }
+        self.apply(&mut writes);
+        writes
+    }
+
+    pub fn swap_cols(&mut self, a: usize, b: usize) -> Vec<Write> {
+        let n = self.grid.n();
+        let mut writes = Vec::with_capacity(2 * n);
+        if a != b {
+            for i in 0..n {
+                writes.push((i, a, self.grid.get(i, b)));
+                writes.push((i, b, self.grid.get(i, a)));
+            }
+        }
+        self.apply(&mut writes);
+        writes
+    }
+
+    /// Cyclically shifts column `j` up by `st`.
+    pub fn rotate_col(&mut self, j: usize, st: usize) -> Vec<Write> {
+        let n = self.grid.n();
+        let st = st % n;
+        let mut writes = Vec::with_capacity(n);
+        if st > 0 {
+            for i in 0..n {
+                writes.push((i, j, self.grid.get((i + st) % n, j)));
+            }
+        }
+        self.apply(&mut writes);
+        writes
+    }
+
+    /// Counts (or uncounts) every pattern starting at `(i, j)` in direction `d`.
+    fn walk(&mut self, i: usize, j: usize, d: usize, add: bool) {
+        let n = self.grid.n();
+        let dict = &*self.dict;
+        let len = dict.max_len;
+        let window = if len <= 16 && len <= n {
+            Some(self.grid.window(i, j, d, len))
+        } else {
+            None
+        };
+        let mut node = 0;
+        for k in 0..len {
+            let c = match window {
+                Some(w) => (w >> (4 * k) & 15) as u8,
+                None if d == 0 => self.grid.get(i, (j + k) % n),
+                None => self.grid.get((i + k) % n, j),
+            };
+            if c == DOT {
+                break;
+            }
+            node = dict.next[node][c as usize] as usize;
+            if node == 0 {
+                break;
+            }
+            let p = dict.terminal[node];
+            if p != NONE {
+                let p = p as usize;
+                if add {
+                    self.count[p] += 1;
+                    if self.count[p] == 1 {
+                        self.covered += dict.weight[p];
+                        let k = self.slot[p] as usize;
+                        self.uncovered.swap_remove(k);
+                        if let Some(&q) = self.uncovered.get(k) {
+                            self.slot[q as usize] = k as u32;
+                        }
+                        self.slot[p] = NONE;
+                    }
+                } else {
+                    self.count[p] -= 1;
+                    if self.count[p] == 0 {
+                        self.covered -= dict.weight[p];
+                        self.slot[p] = self.uncovered.len() as u32;
+                        self.uncovered.push(p as u32);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+
+    use super::*;
+    use crate::judge;
+    use crate::moves::{self, MoveKind};
+
+    fn random_output<R: Rng>(n: usize, k: usize, rng: &mut R) -> Output {
+        (0..n)
+            .map(|_| {
+                (0..n)
+                    .map(|_| match rng.gen_range(0, 10) {
+                        0 => '.',
+                        _ => (b'A' + rng.gen_range(0, k as u8)) as char,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn assert_judge_score(input: &Input, eval: &Evaluator) {
+        let (score, reason) = judge::compute_score_detail(input, &eval.to_output());
+        assert_eq!(reason, "");
+        assert_eq!(eval.score(), score);
+        let mut uncovered = eval.uncovered().to_vec();
+        uncovered.sort_unstable();
+        let expected: Vec<u32> = (0..eval.dict.patterns.len())
+            .filter(|&p| !eval.is_covered(p))
+            .map(|p| p as u32)
+            .collect();
+        assert_eq!(uncovered, expected);
+    }
+
+    #[test]
+    fn matches_judge_under_random_moves() {
+        let mut rng = SmallRng::seed_from_u64(1);
+        // the judge rescans every pattern, so the large grid gets fewer steps
+        for &(n, k, steps) in &[(20, 8, 20), (6, 3, 300), (4, 2, 300)] {
+            for seed in 0..3 {
+                let input = judge::gen_sized(seed, n, k);
+                let mut eval = Evaluator::new(&input, &random_output(n, k, &mut rng));
+                assert_judge_score(&input, &eval);
+                for _ in 0..steps {
+                    let before = (eval.grid().clone(), eval.score());
+                    let mut undo = if rng.gen_range(0, 4) == 0 {
+                        let mut writes = vec![(rng.gen_range(0, n), rng.gen_range(0, n), DOT)];
+                        eval.apply(&mut writes);
+                        writes
+                    } else {
+                        let kind = *MoveKind::ALL.choose(&mut rng).unwrap();
+                        match moves::apply_random(kind, &mut eval, &mut rng) {
+                            Some(undo) => undo,
+                            None => continue,
+                        }
+                    };
+                    assert_judge_score(&input, &eval);
+                    if rng.gen() {
+                        eval.apply(&mut undo);
+                        assert_judge_score(&input, &eval);
+                        assert!((eval.grid().clone(), eval.score()) == before);
+                    }
+                }
+            }
+        }
+    }
+}
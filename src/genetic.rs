@@ -0,0 +1,230 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use rand::prelude::*;
+
+use crate::evaluator::Dictionary;
+use crate::grid::Grid;
+use crate::judge::{Input, Output, Scorer};
+use crate::schedule::Budget;
+use crate::telemetry::Sampler;
+
+/// Upper bound on the row rotations and cell edits applied to a child.
+const MUTATIONS: usize = 2;
+/// Parents are the best of this many random individuals.
+const TOURNAMENT: usize = 3;
+
+/// Steady-state genetic algorithm over whole grids. A child takes the rows of
+/// two parents that add the most weight of patterns not yet covered by the
+/// rows taken before, then gets a few row rotations and cell edits. It
+/// replaces the worst individual if it scores higher and is not in the
+/// population yet.
+pub struct Population {
+    dict: Arc<Dictionary>,
+    scorer: Scorer,
+    alphabet: u8,
+    individuals: Vec<(Grid, i64)>,
+    pub generation: usize,
+    pub replaced: usize,
+}
+
+impl Population {
+    /// `first` and `size - 1` copies of it with every row rotated at random
+    /// and a few cells edited.
+    pub fn new<R: Rng>(
+        input: &Input,
+        dict: Arc<Dictionary>,
+        first: Grid,
+        size: usize,
+        alphabet: u8,
+        rng: &mut R,
+    ) -> Population {
+        let mut population = Population {
+            dict,
+            scorer: Scorer::new(input),
+            alphabet,
+            individuals: Vec::with_capacity(size),
+            generation: 0,
+            replaced: 0,
+        };
+        let score = population.score(&first);
+        population.individuals.push((first.clone(), score));
+        while population.individuals.len() < size {
+            let mut grid = first.clone();
+            for i in 0..grid.n() {
+                grid.rotate_row(i, rng.gen_range(0, grid.n()));
+            }
+            population.mutate(&mut grid, rng);
+            let score = population.score(&grid);
+            population.individuals.push((grid, score));
+        }
+        population
+    }
+
+    pub fn best(&self) -> &(Grid, i64) {
+        self.individuals.iter().max_by_key(|(_, s)| *s).unwrap()
+    }
+
+    /// Breeds one child per step until `budget` is used up.
+    pub fn evolve<R: Rng>(&mut self, budget: Budget, rng: &mut R) {
+        let mut sampler = Sampler::new();
+        let from = Instant::now();
+        let first = self.generation;
+        while budget.progress(from, self.generation - first).is_some() {
+            let now = Instant::now();
+            let best = self.best().1;
+            sampler.sample(now, "ga", self.generation, best, best);
+            self.generation += 1;
+            let a = self.select(rng);
+            let b = self.select(rng);
+            let mut child = self.crossover(&self.individuals[a].0, &self.individuals[b].0, rng);
+            self.mutate(&mut child, rng);
+            let score = self.score(&child);
+
+            let worst = (0..self.individuals.len())
+                .min_by_key(|&k| self.individuals[k].1)
+                .unwrap();
+            if score > self.individuals[worst].1
+                && self.individuals.iter().all(|(g, _)| *g != child)
+            {
+                self.individuals[worst] = (child, score);
+                self.replaced += 1;
+            }
+        }
+    }
+
+    fn score(&self, grid: &Grid) -> i64 {
+        self.scorer.compute_score_detail(&Output::from(grid)).0
+    }
+
+    fn select<R: Rng>(&self, rng: &mut R) -> usize {
+        (0..TOURNAMENT)
+            .map(|_| rng.gen_range(0, self.individuals.len()))
+            .max_by_key(|&k| self.individuals[k].1)
+            .unwrap()
+    }
+
+    /// Takes `n` of the `2n` rows of `a` and `b` greedily by the weight of
+    /// the patterns they add (ties at random). A row goes back to its index
+    /// in the parent when that is free, so columns stay mostly aligned.
+    fn crossover<R: Rng>(&self, a: &Grid, b: &Grid, rng: &mut R) -> Grid {
+        let n = a.n();
+        let dict = &*self.dict;
+        let mut rows: Vec<(usize, Vec<u8>, Vec<usize>)> = [a, b]
+            .iter()
+            .flat_map(|g| (0..n).map(move |i| (i, (0..n).map(|j| g.get(i, j)).collect())))
+            .map(|(i, row): (usize, Vec<u8>)| {
+                let found = dict.occurring(&row);
+                (i, row, found)
+            })
+            .collect();
+        rows.shuffle(rng);
+
+        let mut covered = vec![false; dict.patterns.len()];
+        let mut slots: Vec<Option<Vec<u8>>> = vec![None; n];
+        for _ in 0..n {
+            let (k, _) = rows
+                .iter()
+                .enumerate()
+                .map(|(k, (_, _, found))| {
+                    let gain: usize = found
+                        .iter()
+                        .filter(|&&p| !covered[p])
+                        .map(|&p| dict.weight[p])
+                        .sum();
+                    (k, gain)
+                })
+                .fold(None, |best: Option<(usize, usize)>, (k, gain)| match best {
+                    Some((_, g)) if g >= gain => best,
+                    _ => Some((k, gain)),
+                })
+                .unwrap();
+            let (i, row, found) = rows.swap_remove(k);
+            for p in found {
+                covered[p] = true;
+            }
+            let slot = if slots[i].is_none() {
+                i
+            } else {
+                slots.iter().position(|s| s.is_none()).unwrap()
+            };
+            slots[slot] = Some(row);
+        }
+
+        let mut child = Grid::new(n);
+        for (i, row) in slots.into_iter().enumerate() {
+            for (j, v) in row.unwrap().into_iter().enumerate() {
+                child.set(i, j, v);
+            }
+        }
+        child
+    }
+
+    fn mutate<R: Rng>(&self, grid: &mut Grid, rng: &mut R) {
+        let n = grid.n();
+        for _ in 0..rng.gen_range(0, MUTATIONS + 1) {
+            if rng.gen() {
+                grid.rotate_row(rng.gen_range(0, n), rng.gen_range(1, n));
+            } else {
+                grid.set(
+                    rng.gen_range(0, n),
+                    rng.gen_range(0, n),
+                    rng.gen_range(0, self.alphabet),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judge;
+
+    fn rows(grid: &Grid) -> Vec<Vec<u8>> {
+        let n = grid.n();
+        (0..n)
+            .map(|i| (0..n).map(|j| grid.get(i, j)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn crossover_takes_whole_rows_that_add_coverage() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let (input, answer) = judge::gen_with_answer(0);
+        let dict = Arc::new(Dictionary::new(&input));
+        let planted = Grid::from(&answer.a);
+        let population = Population::new(&input, dict.clone(), planted.clone(), 4, 8, &mut rng);
+
+        // every child row is a row of one of the parents
+        for _ in 0..10 {
+            let a = &population.individuals[rng.gen_range(0, 4)].0;
+            let b = &population.individuals[rng.gen_range(0, 4)].0;
+            let child = population.crossover(a, b, &mut rng);
+            let parents = [rows(a), rows(b)].concat();
+            for row in rows(&child) {
+                assert!(parents.contains(&row));
+            }
+        }
+
+        // crossing the planted answer with itself offers every row twice;
+        // once a row is taken its copy adds nothing, while each row not
+        // taken yet still adds a pattern no other row has
+        let planted_rows = rows(&planted);
+        for (i, row) in planted_rows.iter().enumerate() {
+            let others: Vec<usize> = planted_rows
+                .iter()
+                .enumerate()
+                .filter(|&(k, _)| k != i)
+                .flat_map(|(_, other)| dict.occurring(other))
+                .collect();
+            assert!(dict.occurring(row).iter().any(|p| !others.contains(p)));
+        }
+        let child = population.crossover(&planted, &planted, &mut rng);
+        let mut child_rows = rows(&child);
+        let mut expected = planted_rows.clone();
+        child_rows.sort();
+        expected.sort();
+        assert_eq!(child_rows, expected);
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::judge::Output;
+
+/// Cell value of '.'; letters are stored as `c - b'A'` in the low 3 bits and
+/// this value is the dot flag.
+pub const DOT: u8 = 8;
+
+/// A torus of `n x n` cells packed 4 bits per cell. Cells are stored both
+/// row-major and column-major, so any cyclic run of up to 16 cells along a
+/// row or a column is read with a couple of shifts.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid {
+    n: usize,
+    stride: usize,
+    rows: Vec<u64>,
+    cols: Vec<u64>,
+}
+
+impl Grid {
+    /// A grid filled with 'A'.
+    #[allow(clippy::manual_div_ceil)] // usize::div_ceil needs Rust 1.73
+    pub fn new(n: usize) -> Grid {
+        let stride = (n + 15) / 16;
+        Grid {
+            n,
+            stride,
+            rows: vec![0; n * stride],
+            cols: vec![0; n * stride],
+        }
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> u8 {
+        (self.rows[i * self.stride + j / 16] >> (4 * (j % 16)) & 15) as u8
+    }
+
+    pub fn set(&mut self, i: usize, j: usize, v: u8) {
+        set_nibble(&mut self.rows[i * self.stride + j / 16], j, v);
+        set_nibble(&mut self.cols[j * self.stride + i / 16], i, v);
+    }
+
+    /// The `len <= 16` cells from `(i, j)` going right (`d == 0`) or down
+    /// (`d == 1`), wrapping around; cell `k` is in bits `4k..4k + 4`.
+    pub fn window(&self, i: usize, j: usize, d: usize, len: usize) -> u64 {
+        assert!(len <= 16 && len <= self.n);
+        let (line, from) = if d == 0 {
+            (&self.rows[i * self.stride..(i + 1) * self.stride], j)
+        } else {
+            (&self.cols[j * self.stride..(j + 1) * self.stride], i)
+        };
+        if from + len <= self.n {
+            extract(line, from, len)
+        } else {
+            let first = self.n - from;
+            extract(line, from, first) | extract(line, 0, len - first) << (4 * first)
+        }
+    }
+
+    /// Cyclically shifts row `i` left by `k`.
+    pub fn rotate_row(&mut self, i: usize, k: usize) {
+        let row: Vec<u8> = (0..self.n).map(|j| self.get(i, (j + k) % self.n)).collect();
+        for (j, &v) in row.iter().enumerate() {
+            self.set(i, j, v);
+        }
+    }
+}
+
+fn set_nibble(word: &mut u64, k: usize, v: u8) {
+    let shift = 4 * (k % 16);
+    *word = *word & !(15 << shift) | (v as u64) << shift;
+}
+
+/// `len <= 16` cells starting at `from`, without wrapping.
+fn extract(line: &[u64], from: usize, len: usize) -> u64 {
+    if len == 0 {
+        return 0;
+    }
+    let off = 4 * (from % 16);
+    let mut v = line[from / 16] >> off;
+    if off > 0 && off + 4 * len > 64 {
+        v |= line[from / 16 + 1] << (64 - off);
+    }
+    if len < 16 {
+        v &= (1 << (4 * len)) - 1;
+    }
+    v
+}
+
+fn cell(c: char) -> Option<u8> {
+    match c {
+        '.' => Some(DOT),
+        'A'..='H' => Some(c as u8 - b'A'),
+        _ => None,
+    }
+}
+
+fn letter(v: u8) -> char {
+    if v == DOT {
+        '.'
+    } else {
+        (b'A' + v) as char
+    }
+}
+
+impl From<&Output> for Grid {
+    fn from(out: &Output) -> Grid {
+        let mut grid = Grid::new(out.len());
+        for (i, row) in out.iter().enumerate() {
+            for (j, &c) in row.iter().enumerate() {
+                grid.set(
+                    i,
+                    j,
+                    cell(c).unwrap_or_else(|| panic!("illegal char: {}", c)),
+                );
+            }
+        }
+        grid
+    }
+}
+
+impl From<&Grid> for Output {
+    fn from(grid: &Grid) -> Output {
+        (0..grid.n)
+            .map(|i| (0..grid.n).map(|j| letter(grid.get(i, j))).collect())
+            .collect()
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for i in 0..self.n {
+            let row: String = (0..self.n).map(|j| letter(self.get(i, j))).collect();
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Grid {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Grid, String> {
+        let lines: Vec<&str> = s.split_whitespace().collect();
+        let n = lines.len();
+        let mut grid = Grid::new(n);
+        for (i, line) in lines.iter().enumerate() {
+            if line.chars().count() != n {
+                return Err(format!("illegal length: {}", line.chars().count()));
+            }
+            for (j, c) in line.chars().enumerate() {
+                grid.set(i, j, cell(c).ok_or_else(|| format!("illegal char: {}", c))?);
+            }
+        }
+        Ok(grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+
+    use super::*;
+
+    fn random_grid<R: Rng>(n: usize, rng: &mut R) -> Grid {
+        let mut grid = Grid::new(n);
+        for i in 0..n {
+            for j in 0..n {
+                grid.set(i, j, rng.gen_range(0, DOT + 1));
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn conversions_are_lossless() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for &n in &[1, 2, 15, 16, 17, 20, 33] {
+            let grid = random_grid(n, &mut rng);
+            let out = Output::from(&grid);
+            assert_eq!(out.len(), n);
+            assert!(out.iter().all(|row| row.len() == n));
+            assert_eq!(Grid::from(&out), grid);
+            assert_eq!(grid.to_string().parse::<Grid>(), Ok(grid.clone()));
+        }
+        assert!("AB\nA".parse::<Grid>().is_err());
+        assert!("AB\nAI".parse::<Grid>().is_err());
+    }
+
+    #[test]
+    fn window_matches_get() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for &n in &[15, 16, 17, 33] {
+            let grid = random_grid(n, &mut rng);
+            for i in 0..n {
+                for j in 0..n {
+                    for d in 0..2 {
+                        for len in 0..=n.min(16) {
+                            let expected = (0..len).fold(0, |w, k| {
+                                let v = if d == 0 {
+                                    grid.get(i, (j + k) % n)
+                                } else {
+                                    grid.get((i + k) % n, j)
+                                };
+                                w | (v as u64) << (4 * k)
+                            });
+                            assert_eq!(grid.window(i, j, d, len), expected);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
 #![allow(non_snake_case, dead_code, unused_imports, unused_macros)]
+// Mirrors the official judge in tools/src/lib.rs, so its lints are silenced
+// rather than fixed here.
+#![allow(clippy::ptr_arg, clippy::needless_range_loop, clippy::needless_borrow)]
 
 use rand::prelude::*;
 pub trait SetMinMax {
@@ -30,30 +33,32 @@ macro_rules! mat {
 	($e:expr; $d:expr) => { Vec::from(vec![$e; $d]) };
 	($e:expr; $d:expr $(; $ds:expr)+) => { Vec::from(vec![mat![$e $(; $ds)*]; $d]) };
 }
-pub const N: usize = 20;
+/// Grid size and alphabet size of the contest; inputs may use smaller or
+/// larger grids and fewer letters.
+pub const DEFAULT_N: usize = 20;
+pub const DEFAULT_K: usize = 8;
 
 pub type Output = Vec<Vec<char>>;
 
 pub struct Input {
+    pub N: usize,
+    /// letters 'A'.. 'A' + K - 1 are used, K <= 8
+    pub K: usize,
     pub M: usize,
     pub s: Vec<Vec<char>>,
 }
 
 pub const DIR: [(usize, usize); 2] = [(0, 1), (1, 0)];
 
-pub fn mod_n(i: usize) -> usize {
-    if i >= N {
-        i - N
-    } else {
-        i
-    }
+pub fn mod_n(i: usize, n: usize) -> usize {
+    i % n
 }
 
 pub fn is_substring(a: &Vec<Vec<char>>, b: &Vec<char>, i: usize, j: usize, d: usize) -> bool {
     let (di, dj) = DIR[d];
     for k in 0..b.len() {
-        let i = mod_n(i + di * k);
-        let j = mod_n(j + dj * k);
+        let i = mod_n(i + di * k, a.len());
+        let j = mod_n(j + dj * k, a.len());
         if a[i][j] != b[k] {
             return false;
         }
@@ -65,29 +70,44 @@ pub fn get_substring(a: &Vec<Vec<char>>, i: usize, j: usize, d: usize, k: usize)
     let (di, dj) = DIR[d];
     let mut b = vec![];
     for k in 0..k {
-        let i = mod_n(i + di * k);
-        let j = mod_n(j + dj * k);
+        let i = mod_n(i + di * k, a.len());
+        let j = mod_n(j + dj * k, a.len());
         b.push(a[i][j]);
     }
     b
 }
 
-pub fn compute_score_detail(input: &Input, out: &Output) -> (i64, String) {
-    let mut c = 0;
+/// Checks the shape and the letters of `out`; returns the number of '.' or
+/// the reason it is illegal.
+fn count_dots(N: usize, K: usize, out: &Output) -> Result<usize, String> {
+    let last = (b'A' + K as u8 - 1) as char;
+    if out.len() != N {
+        return Err(format!("illegal number of rows: {}", out.len()));
+    }
     let mut d = 0;
     for i in 0..N {
         if out[i].len() != N {
-            return (0, format!("illegal length: {}", out[i].len()));
+            return Err(format!("illegal length: {}", out[i].len()));
         }
         for j in 0..N {
-            if (out[i][j] < 'A' || 'H' < out[i][j]) && out[i][j] != '.' {
-                return (0, format!("illegal char: {}", out[i][j]));
+            if (out[i][j] < 'A' || last < out[i][j]) && out[i][j] != '.' {
+                return Err(format!("illegal char: {}", out[i][j]));
             }
             if out[i][j] == '.' {
                 d += 1;
             }
         }
     }
+    Ok(d)
+}
+
+pub fn compute_score_detail(input: &Input, out: &Output) -> (i64, String) {
+    let N = input.N;
+    let mut c = 0;
+    let d = match count_dots(N, input.K, out) {
+        Ok(d) => d,
+        Err(reason) => return (0, reason),
+    };
     for k in 0..input.M {
         let mut used = false;
         'find: for i in 0..N {
@@ -112,23 +132,232 @@ pub fn compute_score_detail(input: &Input, out: &Output) -> (i64, String) {
     (score.round() as i64, String::new())
 }
 
+/// Scores outputs with an Aho-Corasick automaton over all patterns of an
+/// `Input`. Build it once per input; `compute_score_detail` then streams the
+/// 2N cyclic lines of an output through the automaton and returns the same
+/// result as the free function `compute_score_detail`.
+pub struct Scorer {
+    N: usize,
+    K: usize,
+    M: usize,
+    max_len: usize,
+    next: Vec<[usize; 8]>,
+    /// distinct patterns ending at each node, following dictionary suffix links
+    out: Vec<Vec<usize>>,
+    /// number of copies of each distinct pattern in the input
+    count: Vec<usize>,
+}
+
+impl Scorer {
+    pub fn new(input: &Input) -> Scorer {
+        let mut next = vec![[0; 8]];
+        let mut out: Vec<Vec<usize>> = vec![vec![]];
+        let mut count = vec![];
+        let mut max_len = 0;
+        for s in &input.s {
+            max_len = max_len.max(s.len());
+            let mut v = 0;
+            for &c in s {
+                let c = (c as u8 - b'A') as usize;
+                if next[v][c] == 0 {
+                    next[v][c] = next.len();
+                    next.push([0; 8]);
+                    out.push(vec![]);
+                }
+                v = next[v][c];
+            }
+            if out[v].is_empty() {
+                out[v].push(count.len());
+                count.push(0);
+            }
+            count[out[v][0]] += 1;
+        }
+        let mut fail = vec![0; next.len()];
+        let mut queue = std::collections::VecDeque::new();
+        for &u in &next[0] {
+            if u != 0 {
+                queue.push_back(u);
+            }
+        }
+        while let Some(v) = queue.pop_front() {
+            let f = fail[v];
+            let inherited = out[f].clone();
+            out[v].extend(inherited);
+            let fallback = next[f];
+            for (u, &w) in next[v].iter_mut().zip(&fallback) {
+                if *u != 0 {
+                    fail[*u] = w;
+                    queue.push_back(*u);
+                } else {
+                    *u = w;
+                }
+            }
+        }
+        Scorer {
+            N: input.N,
+            K: input.K,
+            M: input.M,
+            max_len,
+            next,
+            out,
+            count,
+        }
+    }
+
+    pub fn compute_score_detail(&self, out: &Output) -> (i64, String) {
+        let N = self.N;
+        let d = match count_dots(N, self.K, out) {
+            Ok(d) => d,
+            Err(reason) => return (0, reason),
+        };
+        let mut used = vec![false; self.count.len()];
+        for &(di, dj) in &DIR {
+            for s in 0..N {
+                // each line is read N + max_len - 1 characters long so that
+                // occurrences across the seam are seen as well
+                let mut v = 0;
+                for k in 0..N + self.max_len.max(1) - 1 {
+                    let i = (s * dj + k * di) % N;
+                    let j = (s * di + k * dj) % N;
+                    if out[i][j] == '.' {
+                        v = 0;
+                        continue;
+                    }
+                    v = self.next[v][(out[i][j] as u8 - b'A') as usize];
+                    for &p in &self.out[v] {
+                        used[p] = true;
+                    }
+                }
+            }
+        }
+        let c: usize = (0..self.count.len())
+            .filter(|&p| used[p])
+            .map(|p| self.count[p])
+            .sum();
+        let score = if c < self.M {
+            1e8 * c as f64 / self.M as f64
+        } else {
+            1e8 * (2 * N * N) as f64 / (2 * N * N - d) as f64
+        };
+        (score.round() as i64, String::new())
+    }
+}
+
 pub fn gen(seed: u64) -> Input {
+    gen_sized(seed, DEFAULT_N, DEFAULT_K)
+}
+
+/// `gen` on an `N x N` grid with `K` letters; `gen_sized(seed, 20, 8)` is
+/// `gen(seed)`.
+pub fn gen_sized(seed: u64, N: usize, K: usize) -> Input {
+    gen_sized_with_answer(seed, N, K).0
+}
+
+/// The grid `gen` cuts the patterns out of, and where it cuts each of them:
+/// `s[p]` is `get_substring(&a, i, j, d, s[p].len())` for `pos[p] = (i, j, d)`.
+/// `a` covers every pattern without a '.', so it scores 1e8.
+pub struct Answer {
+    pub a: Output,
+    pub pos: Vec<(usize, usize, usize)>,
+}
+
+/// `gen` together with its planted `Answer`.
+pub fn gen_with_answer(seed: u64) -> (Input, Answer) {
+    gen_sized_with_answer(seed, DEFAULT_N, DEFAULT_K)
+}
+
+pub fn gen_sized_with_answer(seed: u64, N: usize, K: usize) -> (Input, Answer) {
+    assert!((1..=8).contains(&K));
     let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(seed);
     let mut a = mat!['.'; N; N];
     for i in 0..N {
         for j in 0..N {
-            a[i][j] = (b'A' + rng.gen_range(0, 8)) as char;
+            a[i][j] = (b'A' + rng.gen_range(0, K as u8)) as char;
         }
     }
     let L = rng.gen_range(4, 11);
     let M = rng.gen_range(400, 801) as usize;
     let mut s = vec![];
+    let mut pos = vec![];
     for _ in 0..M {
         let i = rng.gen_range(0, N as u32) as usize;
         let j = rng.gen_range(0, N as u32) as usize;
         let d = rng.gen_range(0, 2) as usize;
         let k = rng.gen_range(L - 2, L + 3) as usize;
         s.push(get_substring(&a, i, j, d, k));
+        pos.push((i, j, d));
+    }
+    (Input { N, K, M, s }, Answer { a, pos })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::Evaluator;
+
+    fn assert_same(input: &Input, out: &Output) {
+        assert_eq!(
+            Scorer::new(input).compute_score_detail(out),
+            compute_score_detail(input, out)
+        );
+    }
+
+    #[test]
+    fn planted_answer_is_complete() {
+        for seed in 0..5 {
+            let (input, answer) = gen_with_answer(seed);
+            for (s, &(i, j, d)) in input.s.iter().zip(&answer.pos) {
+                assert_eq!(*s, get_substring(&answer.a, i, j, d, s.len()));
+            }
+            let scorer = Scorer::new(&input);
+            assert_eq!(scorer.compute_score_detail(&answer.a).0, 100_000_000);
+            let eval = Evaluator::new(&input, &answer.a);
+            assert_eq!(eval.score(), 100_000_000);
+        }
+    }
+
+    #[test]
+    fn scorer_matches_compute_score_detail() {
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0);
+        for &(N, K) in &[(20, 8), (6, 3), (4, 2)] {
+            for seed in 0..5 {
+                let (input, answer) = gen_sized_with_answer(seed, N, K);
+                assert_same(&input, &answer.a);
+                for &dots in &[0, 1, 5, 50] {
+                    let mut out = answer.a.clone();
+                    for i in 0..N {
+                        for j in 0..N {
+                            if rng.gen_range(0, 100) < dots {
+                                out[i][j] = '.';
+                            } else if rng.gen_range(0, 4) == 0 {
+                                out[i][j] = (b'A' + rng.gen_range(0, K as u8)) as char;
+                            }
+                        }
+                    }
+                    assert_same(&input, &out);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn scorer_rejects_like_compute_score_detail() {
+        let (input, answer) = gen_sized_with_answer(0, 6, 3);
+        let mut short = answer.a.clone();
+        short.pop();
+        let mut long = answer.a.clone();
+        long.push(answer.a[0].clone());
+        let mut narrow = answer.a.clone();
+        narrow[2].pop();
+        let mut above_k = answer.a.clone();
+        above_k[1][4] = 'D';
+        let mut lower = answer.a.clone();
+        lower[0][0] = 'a';
+        for out in &[short, long, narrow, above_k, lower] {
+            let (score, reason) = compute_score_detail(&input, out);
+            assert_eq!(score, 0);
+            assert!(!reason.is_empty());
+            assert_same(&input, out);
+        }
     }
-    Input { M, s }
 }
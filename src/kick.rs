@@ -0,0 +1,198 @@
+use rand::prelude::*;
+use rand::seq::index;
+use rustc_hash::FxHashSet;
+
+use crate::config::{Kick, SolverConfig};
+use crate::construct::{self, close_row, greedy_row};
+use crate::evaluator::{Evaluator, Write};
+use crate::grid::DOT;
+use crate::overlap::OverlapTable;
+
+/// First patterns tried by `greedy_row` for each rebuilt row.
+const FIRSTS: usize = 16;
+
+/// Perturbs a stagnating state so the annealing can leave its basin. Row
+/// kicks rebuild rows with the construction's `greedy_row`, so the tables
+/// it needs are built once here, indexed like `Dictionary::patterns`.
+pub struct Kicker {
+    kind: Kick,
+    size: usize,
+    alphabet: u8,
+    pattern_strs: Vec<String>,
+    weight: Vec<usize>,
+    includes: Vec<FxHashSet<usize>>,
+    included_by: Vec<Vec<usize>>,
+    overlap: OverlapTable,
+}
+
+impl Kicker {
+    pub fn new(eval: &Evaluator, config: &SolverConfig) -> Kicker {
+        let dict = &eval.dict;
+        let alphabet = config.alphabet.min(dict.alphabet);
+        let pattern_strs: Vec<String> = dict
+            .patterns
+            .iter()
+            .map(|p| p.iter().map(|&c| (b'A' + c) as char).collect())
+            .collect();
+        let (includes, included_by) = construct::inclusions(&pattern_strs);
+        Kicker {
+            kind: config.kick,
+            size: config.kick_size,
+            alphabet,
+            overlap: OverlapTable::new(&dict.patterns),
+            weight: dict.weight.clone(),
+            pattern_strs,
+            includes,
+            included_by,
+        }
+    }
+
+    pub fn kick<R: Rng>(&self, eval: &mut Evaluator, rng: &mut R) {
+        match self.kind {
+            Kick::Rows => self.rebuild_rows(eval, rng),
+            Kick::Block => self.randomize_block(eval, rng),
+        }
+    }
+
+    /// Clears `size` random rows, then rebuilds them one by one like the
+    /// construction does, from the patterns no other row covers.
+    fn rebuild_rows<R: Rng>(&self, eval: &mut Evaluator, rng: &mut R) {
+        let n = eval.grid().n();
+        let rows = index::sample(rng, n, self.size.min(n)).into_vec();
+        let mut writes: Vec<Write> = rows
+            .iter()
+            .flat_map(|&i| (0..n).map(move |j| (i, j, DOT)))
+            .collect();
+        eval.apply(&mut writes);
+
+        let m = self.pattern_strs.len();
+        let mut used: FxHashSet<usize> = (0..m).filter(|&p| eval.is_covered(p)).collect();
+        let mut includes: Vec<FxHashSet<usize>> = self
+            .includes
+            .iter()
+            .map(|inc| inc.difference(&used).copied().collect())
+            .collect();
+        let mut include_weight: Vec<usize> = includes
+            .iter()
+            .map(|inc| construct::total_weight(inc, &self.weight))
+            .collect();
+
+        for i in rows {
+            let firsts: Vec<usize> = (0..m)
+                .filter(|idx| !used.contains(idx))
+                .filter(|&idx| self.included_by[idx].is_empty())
+                .collect();
+            let (row, using) = firsts
+                .choose_multiple(rng, FIRSTS)
+                .map(|&idx| {
+                    greedy_row(
+                        idx,
+                        &used,
+                        &self.pattern_strs,
+                        &self.weight,
+                        &includes,
+                        &include_weight,
+                        &self.overlap,
+                        n,
+                    )
+                })
+                .max_by_key(|(_, using)| construct::total_weight(using, &self.weight))
+                .unwrap_or_default();
+            let (row, using) = close_row(
+                &row,
+                n,
+                using,
+                &used,
+                &self.pattern_strs,
+                &self.weight,
+                &include_weight,
+                self.alphabet,
+                rng,
+            );
+
+            let mut writes: Vec<Write> = row
+                .iter()
+                .enumerate()
+                .map(|(j, &c)| (i, j, c as u8 - b'A'))
+                .collect();
+            eval.apply(&mut writes);
+            for x in using {
+                used.insert(x);
+                for &k in &self.included_by[x] {
+                    if includes[k].remove(&x) {
+                        include_weight[k] -= self.weight[x];
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fills a `size x size` square at a random position with random letters.
+    fn randomize_block<R: Rng>(&self, eval: &mut Evaluator, rng: &mut R) {
+        let n = eval.grid().n();
+        let size = self.size.min(n);
+        let (top, left) = (rng.gen_range(0, n), rng.gen_range(0, n));
+        let mut writes: Vec<Write> = Vec::new();
+        for di in 0..size {
+            for dj in 0..size {
+                let c = rng.gen_range(0, self.alphabet);
+                writes.push(((top + di) % n, (left + dj) % n, c));
+            }
+        }
+        eval.apply(&mut writes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annealing::Chain;
+    use crate::judge::{self, Scorer};
+    use crate::moves::MoveKind;
+    use crate::schedule::Budget;
+
+    #[test]
+    fn kicks_keep_the_grid_valid() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        let (input, answer) = judge::gen_with_answer(0);
+        let scorer = Scorer::new(&input);
+        for kind in [Kick::Rows, Kick::Block] {
+            let config = SolverConfig {
+                kick: kind,
+                kick_size: 3,
+                ..SolverConfig::default()
+            };
+            let mut eval = Evaluator::new(&input, &answer.a);
+            let kicker = Kicker::new(&eval, &config);
+            for _ in 0..5 {
+                kicker.kick(&mut eval, &mut rng);
+                let out = eval.to_output();
+                assert!(out.iter().flatten().all(|&c| ('A'..='H').contains(&c)));
+                assert_eq!(
+                    scorer.compute_score_detail(&out),
+                    (eval.score(), String::new())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn kicked_chain_returns_its_best_state() {
+        let (input, answer) = judge::gen_with_answer(0);
+        let eval = Evaluator::new(&input, &answer.a);
+        let kicker = Kicker::new(&eval, &SolverConfig::default());
+        let mut chain = Chain::new(eval, 0);
+        chain.anneal_with_kicks(
+            &MoveKind::ALL,
+            Budget::Steps(200),
+            |_| 1e5,
+            20,
+            |eval, rng| kicker.kick(eval, rng),
+        );
+        assert!(chain.kicks > 0);
+        // the planted answer covers every pattern, nothing scores higher
+        assert_eq!(chain.best_score, 100_000_000);
+        let best = Evaluator::from_grid(chain.eval.dict.clone(), chain.best.clone());
+        assert_eq!(best.score(), chain.best_score);
+    }
+}
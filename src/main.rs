@@ -1,63 +1,56 @@
+mod annealing;
+mod config;
+mod construct;
+mod dots;
+mod evaluator;
+mod genetic;
+mod grid;
 mod judge;
+mod kick;
+mod moves;
+mod overlap;
+mod schedule;
+mod solver;
+mod tabu;
+mod telemetry;
+mod tempering;
 mod text_scanner;
 
-const LEN: u8 = 20u8;
-
 use rand::distributions::{Distribution, Standard};
 use rand::prelude::*;
-use rustc_hash::FxHashSet;
-use std::collections::HashSet;
-use std::ops::Index;
-use std::ops::IndexMut;
-use std::ops::Neg;
-use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
 use std::time::Instant;
-use text_scanner::scan;
-
-use crate::judge::{compute_score_detail, Input, Output};
-
-struct Matrix<T>(Vec<Vec<T>>);
+use text_scanner::{scan, scanln_iter};
 
-impl<T> Index<Pos> for Matrix<T> {
-    type Output = T;
-
-    fn index(&self, index: Pos) -> &Self::Output {
-        &self.0[index.r as usize][index.c as usize]
-    }
-}
-
-impl<T> IndexMut<Pos> for Matrix<T> {
-    fn index_mut(&mut self, index: Pos) -> &mut Self::Output {
-        &mut self.0[index.r as usize][index.c as usize]
-    }
-}
+use crate::config::SolverConfig;
+use crate::judge::{Input, Output};
 
 #[derive(Debug, Clone, Copy)]
 struct Pos {
     r: u8,
     c: u8,
 }
-impl Distribution<Pos> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, r: &mut R) -> Pos {
-        Pos {
-            r: r.gen_range(0, LEN) as u8,
-            c: r.gen_range(0, LEN) as u8,
-        }
-    }
-}
-
 impl Pos {
     fn new(r: u8, c: u8) -> Pos {
         Pos { r, c }
     }
-    fn next(self, dir: Dir) -> Pos {
+    /// A uniformly random cell of an `n x n` grid.
+    fn random<R: Rng + ?Sized>(r: &mut R, n: u8) -> Pos {
+        Pos {
+            r: r.gen_range(0, n),
+            c: r.gen_range(0, n),
+        }
+    }
+    fn next(self, dir: Dir, n: u8) -> Pos {
         match dir {
             Dir::H => Pos {
                 r: self.r,
-                c: (self.c + 1) % LEN as u8,
+                c: (self.c + 1) % n,
             },
             Dir::V => Pos {
-                r: (self.r + 1) % LEN as u8,
+                r: (self.r + 1) % n,
                 c: self.c,
             },
         }
@@ -83,205 +76,172 @@ impl Distribution<Dir> for Standard {
     }
 }
 
-fn find_maximum_prefix(row: &str, new: &str) -> usize {
-    for i in (0..=(row.len().min(new.len()))).rev() {
-        if row[row.len() - i..] == new[0..i] {
-            return i;
-        }
-    }
-    unreachable!();
+fn solve(name: &str, input: &Input, config: &SolverConfig) -> Output {
+    let deadline = Instant::now() + config.time_limit;
+    let solver = solver::by_name(name, config).unwrap();
+    let mut rng = SmallRng::seed_from_u64(config.seed);
+    solver.solve(input, deadline, &mut rng)
 }
 
-fn greedy_row(
-    first: usize,
-    used: &FxHashSet<usize>,
-    pattern_strs: &[String],
-    includes: &Vec<FxHashSet<usize>>,
-) -> (String, FxHashSet<usize>) {
-    let m = pattern_strs.len();
-
-    let mut row = String::new();
-    let mut local_used = FxHashSet::default();
-
-    row += &pattern_strs[first];
-    local_used.insert(first);
-    local_used.extend(includes[first].iter());
-
-    while let Some((next, com_len)) = (0..m)
-        .filter(|idx| !used.contains(idx) && !local_used.contains(idx))
-        .map(|idx| (idx, find_maximum_prefix(&row, &pattern_strs[idx])))
-        .filter(|(idx, com_len)| row.len() + pattern_strs[*idx].len() - com_len <= LEN as usize)
-        .max_by_key(|&(idx, com_len)| {
-            (
-                if com_len == pattern_strs[idx].len() {
-                    1
-                } else {
-                    0
-                },
-                com_len,
-                ((pattern_strs[idx].len() - com_len) as i32).neg(),
-                includes[idx].len(),
-            )
-        })
-    {
-        row += &pattern_strs[next][com_len..];
-        assert!(row.len() <= LEN as usize);
-        local_used.insert(next);
-        local_used.extend(includes[next].iter());
-    }
-
-    (row, local_used)
-}
-
-fn solve(input: &judge::Input, time_limit: Duration) -> judge::Output {
-    let start = Instant::now();
-
-    let mut m = input.M;
-
-    let mut pattern_strs: FxHashSet<String> = FxHashSet::default();
-    for i in 0..m {
-        let s: String = input.s[i].iter().collect::<String>();
-        pattern_strs.insert(s.clone());
-    }
-
-    let mut pattern_strs: Vec<String> = pattern_strs.into_iter().collect::<Vec<_>>();
-    pattern_strs.sort_by_key(|s| s.len());
-    m = pattern_strs.len();
-
-    let mut patterns: Vec<Vec<u8>> = Vec::new();
-    for s in &pattern_strs {
-        let s: Vec<char> = s.chars().collect();
-        let mut p = Vec::new();
-        for c in s {
-            p.push(c as u8 - b'A');
+/// Solves seeds `0..100` with every solver in `config.solvers` on
+/// `config.threads` workers and reports the scores in plain text, or as
+/// `result` and `summary` records with telemetry on. Every `solve` call
+/// measures its own time limit, so run at most one thread per core to keep
+/// scores comparable with a sequential run.
+fn local_test(config: &SolverConfig) {
+    let num = 100;
+    if let Ok(cores) = thread::available_parallelism() {
+        if config.threads > cores.get() && config.telemetry {
+            telemetry::record("warning")
+                .str("message", "time limits will overlap")
+                .int("threads", config.threads as i64)
+                .int("cores", cores.get() as i64)
+                .emit();
+        } else if config.threads > cores.get() {
+            eprintln!(
+                "warning: {} threads on {} cores, time limits will overlap",
+                config.threads, cores
+            );
         }
-        patterns.push(p);
     }
-
-    // TODO: handle equivalent strings get more scores
-
-    let mut includes: Vec<FxHashSet<usize>> = vec![FxHashSet::default(); m];
-    let mut included_by: Vec<Vec<usize>> = vec![Vec::new(); m];
-    for i in 0..m {
-        for j in i + 1..m {
-            if pattern_strs[j].contains(&pattern_strs[i]) {
-                includes[j].insert(i);
-                included_by[i].push(j);
+    let next = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::channel();
+    let workers: Vec<_> = (0..config.threads)
+        .map(|_| {
+            let next = Arc::clone(&next);
+            let tx = tx.clone();
+            let config = config.clone();
+            thread::spawn(move || loop {
+                let seed = next.fetch_add(1, Ordering::SeqCst);
+                if seed >= num {
+                    break;
+                }
+                let input = judge::gen_sized(seed as u64, config.gen_size, config.gen_letters);
+                let scorer = judge::Scorer::new(&input);
+                let scores: Vec<i64> = config
+                    .solvers
+                    .iter()
+                    .map(|name| {
+                        let output = solve(name, &input, &config);
+                        let (score, reason) = scorer.compute_score_detail(&output);
+                        if !reason.is_empty() {
+                            panic!("{}: reason = {}", name, reason);
+                        }
+                        score
+                    })
+                    .collect();
+                tx.send((seed, scores)).unwrap();
+            })
+        })
+        .collect();
+    drop(tx);
+
+    let mut results = vec![None; num];
+    let mut printed = 0;
+    for (seed, scores) in rx {
+        results[seed] = Some(scores);
+        while printed < num {
+            match &results[printed] {
+                Some(scores) if config.telemetry => {
+                    for (name, &score) in config.solvers.iter().zip(scores) {
+                        telemetry::record("result")
+                            .str("solver", name)
+                            .int("seed", config.seed as i64)
+                            .int("input", printed as i64)
+                            .int("score", score)
+                            .emit();
+                    }
+                }
+                Some(scores) => {
+                    let scores: Vec<String> = config
+                        .solvers
+                        .iter()
+                        .zip(scores)
+                        .map(|(name, score)| format!("{} = {}", name, score))
+                        .collect();
+                    eprintln!(
+                        "seed = {:02}, solver_seed = {}, {}",
+                        printed,
+                        config.seed,
+                        scores.join(", ")
+                    );
+                }
+                None => break,
             }
+            printed += 1;
         }
     }
-
-    let mut rng = thread_rng();
-
-    let mut used = FxHashSet::default();
-
-    let mut answer = Vec::new();
-    for _ in 0..LEN {
-        let (_, (mut row, using)) = (0..m)
-            .filter(|idx| !used.contains(idx))
-            .filter(|&idx| included_by[idx].is_empty())
-            .map(|idx| (idx, greedy_row(idx, &used, &pattern_strs, &includes)))
-            .max_by_key(|(_, (_, using))| using.len())
-            .unwrap();
-
-        for x in using {
-            used.insert(x);
-            for &i in &included_by[x] {
-                includes[i].remove(&x);
-            }
-        }
-
-        let mut rng = thread_rng();
-        while row.len() < LEN as usize {
-            let c = (b'A' + rng.gen_range(0, 8) as u8) as char;
-            row.push(c);
-        }
-
-        answer.push(row.chars().collect());
+    for worker in workers {
+        worker.join().unwrap();
     }
 
-    dbg!(start.elapsed());
-    let mid = start.elapsed().as_secs_f64();
-    let mut iteration = 0;
-    let mut score = compute_score_detail(&input, &answer).0;
-    loop {
-        let ratio = (start.elapsed().as_secs_f64() - mid) / (time_limit.as_secs_f64() - mid);
-        if ratio >= 1.0 {
-            break;
-        }
-
-        let start_temp = 100000.0;
-        let end_temp = 10000.0;
-        let temp = start_temp + (end_temp - start_temp) * ratio;
-
-        iteration += 1;
-        if rng.gen::<bool>() {
-            let idx1 = rng.gen_range(0, LEN) as usize;
-            let idx2 = rng.gen_range(0, LEN) as usize;
-            answer.swap(idx1, idx2);
-            let new_score = compute_score_detail(&input, &answer).0;
-            let diff = new_score as f64 - score as f64;
-            let prob = (diff / temp).exp();
-            if prob > rng.gen::<f64>() {
-                score = new_score;
-            } else {
-                answer.swap(idx1, idx2);
-            }
+    let results: Vec<Vec<i64>> = results.into_iter().map(|s| s.unwrap()).collect();
+    for (k, name) in config.solvers.iter().enumerate() {
+        let mut scores: Vec<i64> = results.iter().map(|s| s[k]).collect();
+        scores.sort_unstable();
+        let median = if num % 2 == 1 {
+            scores[num / 2]
         } else {
-            let idx = rng.gen_range(0, LEN) as usize;
-            let st = rng.gen_range(0, LEN) as usize;
-            let old_row = answer[idx].clone();
-            let mut new_row = Vec::new();
-            for i in 0..LEN {
-                new_row.push(old_row[(i as usize + st) % LEN as usize]);
-            }
-            answer[idx] = new_row;
-            let new_score = compute_score_detail(&input, &answer).0;
-            let diff = new_score as f64 - score as f64;
-            let prob = (diff / temp).exp();
-            if prob > rng.gen::<f64>() {
-                score = new_score;
-            } else {
-                answer[idx] = old_row;
-            }
+            (scores[num / 2 - 1] + scores[num / 2]) / 2
+        };
+        let mean = scores.iter().sum::<i64>() / num as i64;
+        if config.telemetry {
+            telemetry::record("summary")
+                .str("solver", name)
+                .int("seed", config.seed as i64)
+                .int("mean", mean)
+                .int("median", median)
+                .int("min", scores[0])
+                .int("max", scores[num - 1])
+                .emit();
+        } else {
+            eprintln!(
+                "{}: solver_seed = {}, mean = {}, median = {}, min = {}, max = {}",
+                name,
+                config.seed,
+                mean,
+                median,
+                scores[0],
+                scores[num - 1],
+            );
         }
     }
-    dbg!(iteration);
-    dbg!(compute_score_detail(&input, &answer).0);
-
-    answer
 }
 
-fn local_test() {
-    let num = 100;
-    let mut sum = 0i64;
-    for seed in 0..num {
-        let input = judge::gen(seed);
-        let output = solve(&input, Duration::from_secs_f64(1.5));
-        let (score, reason) = judge::compute_score_detail(&input, &output);
-        if reason != "" {
-            panic!("reason = {}", reason);
-        }
-        eprintln!("seed = {:02}, score = {}", seed, score,);
-        sum += score;
+/// Reads an output file (`N` rows, as printed by `main`) to warm start from;
+/// panics unless the judge accepts it. Returns the output and its score.
+fn read_output(path: &str, input: &Input, scorer: &judge::Scorer) -> (Output, i64) {
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    let output: Output = text
+        .split_whitespace()
+        .take(input.N)
+        .map(|row| row.chars().collect())
+        .collect();
+    let (score, reason) = scorer.compute_score_detail(&output);
+    if !reason.is_empty() {
+        panic!("{}: {}", path, reason);
     }
-    eprintln!("{}", sum / num as i64);
+    (output, score)
 }
 
-const LOCAL: bool = false;
-
 fn main() {
-    if LOCAL {
-        local_test();
+    let config = SolverConfig::from_env();
+    telemetry::enable(config.telemetry);
+    config.record().emit();
+    if config.verbose {
+        eprintln!("{:?}", config);
+    }
+    if config.local {
+        local_test(&config);
         return;
     }
 
-    let time_limit = Duration::from_secs_f64(2.9);
-
-    let n: u8 = scan();
-    assert!(n == LEN);
-
-    let m: usize = scan();
+    // "N M" as in the contest, optionally followed by the alphabet size K
+    let header: Vec<usize> = scanln_iter().collect();
+    let n = header[0];
+    let m = header[1];
+    let k = header.get(2).copied().unwrap_or(judge::DEFAULT_K);
+    assert!((2..=255).contains(&n) && (1..=8).contains(&k));
 
     let mut strs = Vec::new();
     for _ in 0..m {
@@ -289,11 +249,71 @@ fn main() {
         strs.push(s.chars().collect());
     }
 
-    let input = Input { M: m, s: strs };
-
-    let output: Output = solve(&input, time_limit);
+    let input = Input {
+        N: n,
+        K: k,
+        M: m,
+        s: strs,
+    };
+
+    let deadline = Instant::now() + config.time_limit;
+    let solver = solver::by_name(&config.solvers[0], &config).unwrap();
+    let scorer = judge::Scorer::new(&input);
+    let warm = config
+        .warm_start
+        .as_ref()
+        .map(|path| read_output(path, &input, &scorer));
+    let mut rng = SmallRng::seed_from_u64(config.seed);
+    let mut output: Output = match &warm {
+        Some((start, _)) => solver.improve(&input, start, deadline, &mut rng),
+        None => solver.solve(&input, deadline, &mut rng),
+    };
+    let score = scorer.compute_score_detail(&output).0;
+    if config.verbose {
+        eprintln!(
+            "solver = {}, seed = {}, score = {}",
+            solver.name(),
+            config.seed,
+            score
+        );
+    }
+    telemetry::record("result")
+        .str("solver", solver.name())
+        .int("seed", config.seed as i64)
+        .int("score", score)
+        .emit();
+
+    if let (Some(path), Some((start, loaded))) = (&config.warm_start, warm) {
+        telemetry::record("warm_start")
+            .str("file", path)
+            .int("loaded", loaded)
+            .int("score", score)
+            .int("written", (score > loaded) as i64)
+            .emit();
+        if config.verbose {
+            eprintln!(
+                "warm start: {} scored {}, {}",
+                path,
+                loaded,
+                if score > loaded {
+                    "overwritten"
+                } else {
+                    "kept"
+                }
+            );
+        }
+        if score > loaded {
+            let text: String = output
+                .iter()
+                .map(|row| row.iter().collect::<String>() + "\n")
+                .collect();
+            std::fs::write(path, text).unwrap_or_else(|e| panic!("{}: {}", path, e));
+        } else {
+            output = start;
+        }
+    }
 
-    for i in 0..LEN {
-        println!("{}", output[i as usize].iter().collect::<String>());
+    for row in &output {
+        println!("{}", row.iter().collect::<String>());
     }
 }
@@ -0,0 +1,297 @@
+use rand::prelude::*;
+
+use crate::evaluator::{Evaluator, Write};
+use crate::grid::DOT;
+use crate::judge::{self, Output};
+use crate::telemetry;
+use crate::{Dir, Pos};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveKind {
+    SwapRows,
+    RotateRow,
+    ChangeCell,
+    WritePattern,
+    SwapSegments,
+    InsertPattern,
+    SwapCols,
+    RotateCol,
+}
+
+impl MoveKind {
+    pub const ALL: [MoveKind; 8] = [
+        MoveKind::SwapRows,
+        MoveKind::RotateRow,
+        MoveKind::ChangeCell,
+        MoveKind::WritePattern,
+        MoveKind::SwapSegments,
+        MoveKind::InsertPattern,
+        MoveKind::SwapCols,
+        MoveKind::RotateCol,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MoveKind::SwapRows => "swap_rows",
+            MoveKind::RotateRow => "rotate_row",
+            MoveKind::ChangeCell => "change_cell",
+            MoveKind::WritePattern => "write_pattern",
+            MoveKind::SwapSegments => "swap_segments",
+            MoveKind::InsertPattern => "insert_pattern",
+            MoveKind::SwapCols => "swap_cols",
+            MoveKind::RotateCol => "rotate_col",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<MoveKind> {
+        MoveKind::ALL.iter().copied().find(|k| k.name() == name)
+    }
+
+    fn index(self) -> usize {
+        MoveKind::ALL.iter().position(|&k| k == self).unwrap()
+    }
+}
+
+/// A move with all its random choices made; `apply` is deterministic, so a
+/// move can be tried, undone and applied again.
+#[derive(Debug, Clone, Copy)]
+pub enum Move {
+    SwapRows(usize, usize),
+    /// Shifts a row left.
+    RotateRow(usize, usize),
+    ChangeCell(usize, usize, u8),
+    /// Writes a pattern from a cell in a direction.
+    WritePattern(usize, Pos, Dir),
+    /// Swaps `len` cells of row `a` from `ca` with those of row `b` from `cb`.
+    SwapSegments {
+        a: usize,
+        b: usize,
+        ca: usize,
+        cb: usize,
+        len: usize,
+    },
+    SwapCols(usize, usize),
+    /// Shifts a column up.
+    RotateCol(usize, usize),
+}
+
+impl Move {
+    /// Draws a random move of the given kind for the current state of `eval`,
+    /// or `None` if the kind has nothing to do (`InsertPattern` once every
+    /// pattern is covered).
+    pub fn random<R: Rng>(kind: MoveKind, eval: &Evaluator, rng: &mut R) -> Option<Move> {
+        Move::random_cached(kind, eval, &mut None, rng)
+    }
+
+    /// `random`, with `output` caching `eval.to_output()` for
+    /// `InsertPattern`: moves drawn for the same state can share it, as long
+    /// as it is reset to `None` whenever `eval` changes.
+    pub fn random_cached<R: Rng>(
+        kind: MoveKind,
+        eval: &Evaluator,
+        output: &mut Option<Output>,
+        rng: &mut R,
+    ) -> Option<Move> {
+        let n = eval.grid().n();
+        let mv = match kind {
+            MoveKind::SwapRows => {
+                let a = rng.gen_range(0, n);
+                Move::SwapRows(a, (a + rng.gen_range(1, n)) % n)
+            }
+            MoveKind::RotateRow => Move::RotateRow(rng.gen_range(0, n), rng.gen_range(1, n)),
+            MoveKind::ChangeCell => {
+                let pos = Pos::random(rng, n as u8);
+                let old = eval.get(pos.r as usize, pos.c as usize);
+                let k = eval.dict.alphabet;
+                let c = if old == DOT {
+                    rng.gen_range(0, k)
+                } else if k > 1 {
+                    (old + rng.gen_range(1, k)) % k
+                } else {
+                    old
+                };
+                Move::ChangeCell(pos.r as usize, pos.c as usize, c)
+            }
+            MoveKind::WritePattern => {
+                let p = uncovered_pattern(eval, rng);
+                let dir: Dir = rng.gen();
+                Move::WritePattern(p, Pos::random(rng, n as u8), dir)
+            }
+            MoveKind::SwapSegments => {
+                let a = rng.gen_range(0, n);
+                let b = (a + rng.gen_range(1, n)) % n;
+                let ca = rng.gen_range(0, n);
+                let cb = rng.gen_range(0, n);
+                let len = rng.gen_range(1, n);
+                Move::SwapSegments { a, b, ca, cb, len }
+            }
+            MoveKind::InsertPattern => {
+                let p = *eval.uncovered().choose(rng)? as usize;
+                let out = output.get_or_insert_with(|| eval.to_output());
+                let (pos, dir) = closest_placement(out, &eval.dict.patterns[p], rng);
+                Move::WritePattern(p, pos, dir)
+            }
+            MoveKind::SwapCols => {
+                let a = rng.gen_range(0, n);
+                Move::SwapCols(a, (a + rng.gen_range(1, n)) % n)
+            }
+            MoveKind::RotateCol => Move::RotateCol(rng.gen_range(0, n), rng.gen_range(1, n)),
+        };
+        Some(mv)
+    }
+
+    /// Applies the move to `eval` and returns the writes that undo it.
+    pub fn apply(self, eval: &mut Evaluator) -> Vec<Write> {
+        let n = eval.grid().n();
+        match self {
+            Move::SwapRows(a, b) => eval.swap_rows(a, b),
+            Move::RotateRow(i, st) => eval.rotate_row(i, st),
+            Move::ChangeCell(i, j, c) => {
+                let mut writes = vec![(i, j, c)];
+                eval.apply(&mut writes);
+                writes
+            }
+            Move::WritePattern(p, mut pos, dir) => {
+                let mut writes = Vec::new();
+                for &c in eval.dict.patterns[p].iter().take(n) {
+                    if eval.get(pos.r as usize, pos.c as usize) != c {
+                        writes.push((pos.r as usize, pos.c as usize, c));
+                    }
+                    pos = pos.next(dir, n as u8);
+                }
+                eval.apply(&mut writes);
+                writes
+            }
+            Move::SwapSegments { a, b, ca, cb, len } => {
+                let mut writes = Vec::with_capacity(2 * len);
+                for k in 0..len {
+                    let ja = (ca + k) % n;
+                    let jb = (cb + k) % n;
+                    writes.push((a, ja, eval.get(b, jb)));
+                    writes.push((b, jb, eval.get(a, ja)));
+                }
+                eval.apply(&mut writes);
+                writes
+            }
+            Move::SwapCols(a, b) => eval.swap_cols(a, b),
+            Move::RotateCol(j, st) => eval.rotate_col(j, st),
+        }
+    }
+}
+
+/// A random pattern `eval` does not cover yet, or any pattern once every
+/// one is covered.
+fn uncovered_pattern<R: Rng>(eval: &Evaluator, rng: &mut R) -> usize {
+    match eval.uncovered().choose(rng) {
+        Some(&p) => p as usize,
+        None => rng.gen_range(0, eval.dict.patterns.len()),
+    }
+}
+
+/// The placement of pattern `p` that differs from the grid `out` in the
+/// fewest cells (ties at random), so writing it there breaks little.
+fn closest_placement<R: Rng>(out: &Output, pattern: &[u8], rng: &mut R) -> (Pos, Dir) {
+    let n = out.len();
+    let pattern: Vec<char> = pattern.iter().map(|&c| (b'A' + c) as char).collect();
+    let mut best = (usize::MAX, 0, (0, 0, 0));
+    for i in 0..n {
+        for j in 0..n {
+            for d in 0..2 {
+                let s = judge::get_substring(out, i, j, d, pattern.len());
+                // stop counting once the placement is worse than the best one
+                let mut dist = 0;
+                for (a, b) in s.iter().zip(&pattern) {
+                    if a != b {
+                        dist += 1;
+                        if dist > best.0 {
+                            break;
+                        }
+                    }
+                }
+                if dist < best.0 {
+                    best = (dist, 1, (i, j, d));
+                } else if dist == best.0 {
+                    best.1 += 1;
+                    if rng.gen_range(0, best.1) == 0 {
+                        best.2 = (i, j, d);
+                    }
+                }
+            }
+        }
+    }
+    let (i, j, d) = best.2;
+    let dir = if d == 0 { Dir::H } else { Dir::V };
+    (Pos::new(i as u8, j as u8), dir)
+}
+
+/// Applies a random move of the given kind to `eval` and returns the writes
+/// that undo it, or `None` if there was no move to apply.
+pub fn apply_random<R: Rng>(
+    kind: MoveKind,
+    eval: &mut Evaluator,
+    rng: &mut R,
+) -> Option<Vec<Write>> {
+    Move::random(kind, eval, rng).map(|mv| mv.apply(eval))
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MoveStats {
+    proposed: [usize; 8],
+    accepted: [usize; 8],
+}
+
+impl MoveStats {
+    pub fn record(&mut self, kind: MoveKind, accepted: bool) {
+        self.proposed[kind.index()] += 1;
+        if accepted {
+            self.accepted[kind.index()] += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &MoveStats) {
+        for i in 0..self.proposed.len() {
+            self.proposed[i] += other.proposed[i];
+            self.accepted[i] += other.accepted[i];
+        }
+    }
+
+    /// Emits a `moves` record per move kind.
+    pub fn emit(&self, search: &str) {
+        for kind in MoveKind::ALL.iter() {
+            let i = kind.index();
+            telemetry::record("moves")
+                .str("search", search)
+                .str("kind", kind.name())
+                .int("proposed", self.proposed[i] as i64)
+                .int("accepted", self.accepted[i] as i64)
+                .emit();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closest_placement_finds_the_broken_occurrence() {
+        let (input, answer) = judge::gen_with_answer(0);
+        let p = (0..input.M).max_by_key(|&p| input.s[p].len()).unwrap();
+        let (i, j, d) = answer.pos[p];
+        let pattern: Vec<u8> = input.s[p].iter().map(|&c| c as u8 - b'A').collect();
+        let mut rng = SmallRng::seed_from_u64(0);
+        for k in 0..pattern.len() {
+            let mut out = answer.a.clone();
+            let n = out.len();
+            let (ci, cj) = if d == 0 {
+                (i, (j + k) % n)
+            } else {
+                ((i + k) % n, j)
+            };
+            out[ci][cj] = if out[ci][cj] == 'A' { 'B' } else { 'A' };
+            let (pos, dir) = closest_placement(&out, &pattern, &mut rng);
+            assert_eq!((pos.r as usize, pos.c as usize), (i, j));
+            assert!(matches!((dir, d), (Dir::H, 0) | (Dir::V, 1)));
+        }
+    }
+}
@@ -0,0 +1,101 @@
+/// Suffix-prefix overlaps between every ordered pair of patterns:
+/// `get(a, b)` is the longest suffix of `a` that is also a prefix of `b`
+/// (what `find_maximum_prefix(a, b)` returns).
+pub struct OverlapTable {
+    m: usize,
+    table: Vec<u8>,
+}
+
+impl OverlapTable {
+    /// Runs one KMP pass of every pattern against each pattern's failure
+    /// function, `O(m * total length)` overall.
+    pub fn new<T: AsRef<[u8]>>(patterns: &[T]) -> OverlapTable {
+        let m = patterns.len();
+        let mut table = vec![0u8; m * m];
+        for (b, pb) in patterns.iter().enumerate() {
+            let pb = pb.as_ref();
+            let fail = failure(pb);
+            for (a, pa) in patterns.iter().enumerate() {
+                let mut k = 0;
+                for &c in pa.as_ref() {
+                    if k == pb.len() {
+                        k = fail[k - 1];
+                    }
+                    while k > 0 && pb[k] != c {
+                        k = fail[k - 1];
+                    }
+                    if pb[k] == c {
+                        k += 1;
+                    }
+                }
+                table[a * m + b] = k as u8;
+            }
+        }
+        OverlapTable { m, table }
+    }
+
+    pub fn get(&self, a: usize, b: usize) -> usize {
+        self.table[a * self.m + b] as usize
+    }
+}
+
+/// `fail[i]` is the length of the longest proper border of `p[..=i]`.
+fn failure(p: &[u8]) -> Vec<usize> {
+    let mut fail = vec![0; p.len()];
+    let mut k = 0;
+    for i in 1..p.len() {
+        while k > 0 && p[i] != p[k] {
+            k = fail[k - 1];
+        }
+        if p[i] == p[k] {
+            k += 1;
+        }
+        fail[i] = k;
+    }
+    fail
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::prelude::*;
+
+    use super::*;
+    use crate::construct::find_maximum_prefix;
+
+    #[test]
+    fn matches_find_maximum_prefix() {
+        let mut rng = SmallRng::seed_from_u64(0);
+        for _ in 0..50 {
+            let mut patterns: Vec<Vec<u8>> = (0..20)
+                .map(|_| {
+                    (0..rng.gen_range(1, 9))
+                        .map(|_| rng.gen_range(0, 2))
+                        .collect()
+                })
+                .collect();
+            // patterns contained in others, including equal ones
+            for _ in 0..10 {
+                let p = patterns.choose(&mut rng).unwrap().clone();
+                let from = rng.gen_range(0, p.len());
+                let to = rng.gen_range(from + 1, p.len() + 1);
+                patterns.push(p[from..to].to_vec());
+            }
+            let strs: Vec<String> = patterns
+                .iter()
+                .map(|p| p.iter().map(|&c| (b'A' + c) as char).collect())
+                .collect();
+            let table = OverlapTable::new(&patterns);
+            for a in 0..patterns.len() {
+                for b in 0..patterns.len() {
+                    assert_eq!(
+                        table.get(a, b),
+                        find_maximum_prefix(&strs[a], &strs[b]),
+                        "{} {}",
+                        strs[a],
+                        strs[b]
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+use crate::telemetry;
+
+/// When a search stops: at an instant, or after a number of steps so that
+/// where it stops does not depend on timing.
+#[derive(Debug, Clone, Copy)]
+pub enum Budget {
+    Until(Instant),
+    Steps(usize),
+}
+
+impl Budget {
+    /// Progress in `[0, 1)` of a search that started at `from` and has taken
+    /// `steps` steps since, or `None` once the budget is used up.
+    pub fn progress(self, from: Instant, steps: usize) -> Option<f64> {
+        match self {
+            Budget::Until(until) => {
+                let now = Instant::now();
+                if now >= until {
+                    None
+                } else {
+                    let span = until.saturating_duration_since(from).as_secs_f64();
+                    Some(now.saturating_duration_since(from).as_secs_f64() / span)
+                }
+            }
+            Budget::Steps(total) => {
+                if steps >= total {
+                    None
+                } else {
+                    Some(steps as f64 / total as f64)
+                }
+            }
+        }
+    }
+}
+
+/// Splits the time up to a deadline between the phases of a solver and
+/// reports how long each phase actually took. With `steps`, searches get a
+/// step budget and nothing is cut off by time, so a run only depends on its
+/// seed (and may overrun the deadline).
+pub struct Schedule {
+    start: Instant,
+    end: Instant,
+    deadline: Instant,
+    mark: Instant,
+    steps: Option<usize>,
+}
+
+impl Schedule {
+    /// Every phase has to be done `margin` before `deadline`.
+    pub fn new(deadline: Instant, margin: Duration, steps: Option<usize>) -> Schedule {
+        let start = Instant::now();
+        let end = deadline.checked_sub(margin).unwrap_or(deadline).max(start);
+        Schedule {
+            start,
+            end,
+            deadline,
+            mark: start,
+            steps,
+        }
+    }
+
+    /// The instant a `share` in `[0, 1]` of the available time has elapsed.
+    fn at(&self, share: f64) -> Instant {
+        self.start + (self.end - self.start).mul_f64(share)
+    }
+
+    /// The instant a phase has to stop at after a `share` of the available
+    /// time, or `None` when the run is limited by steps.
+    pub fn cutoff(&self, share: f64) -> Option<Instant> {
+        match self.steps {
+            Some(_) => None,
+            None => Some(self.at(share)),
+        }
+    }
+
+    /// The budget of a search that has to end after a `share` of the
+    /// available time.
+    pub fn budget(&self, share: f64) -> Budget {
+        match self.steps {
+            Some(steps) => Budget::Steps(steps),
+            None => Budget::Until(self.at(share)),
+        }
+    }
+
+    /// Emits a `phase` record with the time since the previous phase ended.
+    pub fn finish(&mut self, phase: &str) {
+        let now = Instant::now();
+        telemetry::record("phase")
+            .str("name", phase)
+            .float("seconds", (now - self.mark).as_secs_f64())
+            .emit();
+        self.mark = now;
+    }
+
+    /// Emits a `margin` record with the time left before the deadline.
+    pub fn report(&self) {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        telemetry::record("margin")
+            .float("seconds", left.as_secs_f64())
+            .emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_budget_counts_steps() {
+        let from = Instant::now();
+        let budget = Budget::Steps(4);
+        assert_eq!(budget.progress(from, 0), Some(0.0));
+        assert_eq!(budget.progress(from, 2), Some(0.5));
+        assert_eq!(budget.progress(from, 4), None);
+        assert_eq!(budget.progress(from, 5), None);
+    }
+
+    #[test]
+    fn until_budget_ends_at_the_instant() {
+        let from = Instant::now();
+        let progress = Budget::Until(from + Duration::from_secs(3600)).progress(from, 0);
+        assert!(matches!(progress, Some(p) if (0.0..0.01).contains(&p)));
+        assert_eq!(Budget::Until(from).progress(from, 0), None);
+    }
+
+    #[test]
+    fn cutoff_splits_the_time_before_the_margin() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let schedule = Schedule::new(deadline, Duration::from_secs(2), None);
+        let end = deadline - Duration::from_secs(2);
+        assert_eq!(schedule.cutoff(1.0), Some(end));
+        let half = schedule.cutoff(0.5).unwrap();
+        assert!(schedule.start < half && half < end);
+        assert!(matches!(schedule.budget(0.5), Budget::Until(t) if t == half));
+
+        let schedule = Schedule::new(deadline, Duration::from_secs(2), Some(100));
+        assert_eq!(schedule.cutoff(0.5), None);
+        assert!(matches!(schedule.budget(0.5), Budget::Steps(100)));
+    }
+}
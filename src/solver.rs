@@ -0,0 +1,271 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use rand::prelude::*;
+
+use crate::annealing::Chain;
+use crate::config::SolverConfig;
+use crate::construct;
+use crate::dots;
+use crate::evaluator::{Dictionary, Evaluator};
+use crate::genetic::Population;
+use crate::grid::Grid;
+use crate::judge::{Input, Output};
+use crate::kick::Kicker;
+use crate::schedule::Schedule;
+use crate::tabu::TabuSearch;
+use crate::telemetry;
+use crate::tempering;
+
+/// A search strategy. `run` must return before `deadline`.
+pub trait Solver {
+    fn name(&self) -> &'static str;
+
+    /// Searches from `start` if given, otherwise from a newly built grid.
+    fn run(
+        &self,
+        input: &Input,
+        start: Option<&Output>,
+        deadline: Instant,
+        rng: &mut SmallRng,
+    ) -> Output;
+
+    fn solve(&self, input: &Input, deadline: Instant, rng: &mut SmallRng) -> Output {
+        self.run(input, None, deadline, rng)
+    }
+
+    /// Like `solve`, but searches from `start` instead of building a new
+    /// grid.
+    fn improve(
+        &self,
+        input: &Input,
+        start: &Output,
+        deadline: Instant,
+        rng: &mut SmallRng,
+    ) -> Output {
+        self.run(input, Some(start), deadline, rng)
+    }
+}
+
+/// Names accepted by `by_name`.
+pub const NAMES: [&str; 3] = ["sa", "tabu", "ga"];
+
+pub fn by_name(name: &str, config: &SolverConfig) -> Option<Box<dyn Solver>> {
+    match name {
+        "sa" => Some(Box::new(Annealing {
+            config: config.clone(),
+        })),
+        "tabu" => Some(Box::new(Tabu {
+            config: config.clone(),
+        })),
+        "ga" => Some(Box::new(Genetic {
+            config: config.clone(),
+        })),
+        _ => None,
+    }
+}
+
+/// The state a search starts from: `start` if given, otherwise a grid built
+/// by `construct` within its share of the schedule.
+fn initial_state(
+    input: &Input,
+    start: Option<&Output>,
+    config: &SolverConfig,
+    schedule: &mut Schedule,
+    rng: &mut SmallRng,
+) -> Evaluator {
+    match start {
+        Some(start) => Evaluator::new(input, start),
+        None => {
+            let until = schedule.cutoff(config.construct_share);
+            let answer = construct::construct(input, config, rng, until);
+            schedule.finish("construct");
+            Evaluator::new(input, &answer)
+        }
+    }
+}
+
+/// Runs the dot phase on the best grid of a search until the end of the
+/// schedule (or through all its trials when the run is limited by steps).
+fn finish(
+    dict: Arc<Dictionary>,
+    best: Grid,
+    schedule: &mut Schedule,
+    rng: &mut SmallRng,
+) -> Output {
+    let mut best = Evaluator::from_grid(dict, best);
+    dots::maximize_dots(&mut best, rng, schedule.cutoff(1.0));
+    schedule.finish("dots");
+    telemetry::record("dots").int("score", best.score()).emit();
+    schedule.report();
+
+    best.to_output()
+}
+
+/// Greedy row construction followed by simulated annealing (parallel chains
+/// when `config.chains > 1`) and the dot phase.
+pub struct Annealing {
+    config: SolverConfig,
+}
+
+impl Solver for Annealing {
+    fn name(&self) -> &'static str {
+        "sa"
+    }
+
+    fn run(
+        &self,
+        input: &Input,
+        start: Option<&Output>,
+        deadline: Instant,
+        rng: &mut SmallRng,
+    ) -> Output {
+        let config = &self.config;
+        let mut schedule = Schedule::new(deadline, config.margin, config.iterations);
+        let eval = initial_state(input, start, config, &mut schedule, rng);
+        // leave the last 2% for the dot phase
+        let budget = schedule.budget(0.98);
+        let dict = eval.dict.clone();
+        let (best, stats) = if config.chains > 1 {
+            tempering::run(eval, config, rng, budget)
+        } else {
+            // only a chain with patience ever kicks
+            let kicker = if config.patience > 0 {
+                Some(Kicker::new(&eval, config))
+            } else {
+                None
+            };
+            let mut chain = Chain::new(eval, rng.gen());
+            chain.anneal_with_kicks(
+                &config.moves,
+                budget,
+                |ratio| config.start_temp + (config.end_temp - config.start_temp) * ratio,
+                config.patience,
+                |eval, rng| {
+                    if let Some(kicker) = &kicker {
+                        kicker.kick(eval, rng);
+                    }
+                },
+            );
+            telemetry::record("search")
+                .str("search", "sa")
+                .int("iterations", chain.iteration as i64)
+                .int("kicks", chain.kicks as i64)
+                .int("score", chain.score)
+                .int("best", chain.best_score)
+                .emit();
+            (chain.best, chain.stats)
+        };
+        schedule.finish("sa");
+        stats.emit("sa");
+
+        finish(dict, best, &mut schedule, rng)
+    }
+}
+
+/// Same construction and dot phase as `Annealing`, with a tabu search
+/// (`tabu::TabuSearch`) in between.
+pub struct Tabu {
+    config: SolverConfig,
+}
+
+impl Solver for Tabu {
+    fn name(&self) -> &'static str {
+        "tabu"
+    }
+
+    fn run(
+        &self,
+        input: &Input,
+        start: Option<&Output>,
+        deadline: Instant,
+        rng: &mut SmallRng,
+    ) -> Output {
+        let config = &self.config;
+        let mut schedule = Schedule::new(deadline, config.margin, config.iterations);
+        let eval = initial_state(input, start, config, &mut schedule, rng);
+        let dict = eval.dict.clone();
+        let mut search = TabuSearch::new(eval, config.tabu_tenure, config.tabu_samples);
+        search.run(&config.moves, schedule.budget(0.98), rng);
+        telemetry::record("search")
+            .str("search", "tabu")
+            .int("iterations", search.iteration as i64)
+            .int("score", search.score)
+            .int("best", search.best_score)
+            .emit();
+        schedule.finish("tabu");
+        search.stats.emit("tabu");
+
+        finish(dict, search.best, &mut schedule, rng)
+    }
+}
+
+/// Same construction and dot phase as `Annealing`, with a genetic algorithm
+/// (`genetic::Population`) seeded from the constructed grid in between.
+pub struct Genetic {
+    config: SolverConfig,
+}
+
+impl Solver for Genetic {
+    fn name(&self) -> &'static str {
+        "ga"
+    }
+
+    fn run(
+        &self,
+        input: &Input,
+        start: Option<&Output>,
+        deadline: Instant,
+        rng: &mut SmallRng,
+    ) -> Output {
+        let config = &self.config;
+        let mut schedule = Schedule::new(deadline, config.margin, config.iterations);
+        let eval = initial_state(input, start, config, &mut schedule, rng);
+        let dict = eval.dict.clone();
+        let alphabet = config.alphabet.min(dict.alphabet);
+        let mut population = Population::new(
+            input,
+            dict.clone(),
+            eval.grid().clone(),
+            config.population,
+            alphabet,
+            rng,
+        );
+        population.evolve(schedule.budget(0.98), rng);
+        let (best, best_score) = population.best();
+        telemetry::record("search")
+            .str("search", "ga")
+            .int("iterations", population.generation as i64)
+            .int("replaced", population.replaced as i64)
+            .int("best", *best_score)
+            .emit();
+        schedule.finish("ga");
+
+        finish(dict, best.clone(), &mut schedule, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judge::{self, Scorer};
+
+    #[test]
+    fn easy_instance_scores_above_1e8() {
+        // seed 1 has the shortest patterns (L = 4); with its first 150
+        // patterns every one gets covered and the dot phase runs
+        let mut input = judge::gen(1);
+        input.s.truncate(150);
+        input.M = input.s.len();
+        let config = SolverConfig {
+            iterations: Some(20_000),
+            ..SolverConfig::default()
+        };
+        let solver = by_name("sa", &config).unwrap();
+        let mut rng = SmallRng::seed_from_u64(0);
+        let output = solver.solve(&input, Instant::now(), &mut rng);
+        let (score, reason) = Scorer::new(&input).compute_score_detail(&output);
+        assert_eq!(reason, "");
+        assert!(score > 100_000_000, "{}", score);
+    }
+}
@@ -0,0 +1,191 @@
+use std::time::Instant;
+
+use rand::prelude::*;
+
+use crate::evaluator::{Evaluator, Write};
+use crate::grid::{Grid, DOT};
+use crate::moves::{Move, MoveKind, MoveStats};
+use crate::schedule::Budget;
+use crate::telemetry::Sampler;
+
+/// Tabu search state. Undoing a recent move is tabu for `tenure` steps:
+/// rotating a row or column back by the shift it was just rotated by, or
+/// writing a letter back into a cell it was just removed from.
+pub struct TabuSearch {
+    pub eval: Evaluator,
+    pub score: i64,
+    pub best: Grid,
+    pub best_score: i64,
+    pub stats: MoveStats,
+    pub iteration: usize,
+    tenure: usize,
+    samples: usize,
+    /// step until which `(row, shift)` is tabu
+    row_until: Vec<usize>,
+    /// step until which `(column, shift)` is tabu
+    col_until: Vec<usize>,
+    /// step until which `(cell, letter)` is tabu
+    cell_until: Vec<usize>,
+}
+
+impl TabuSearch {
+    pub fn new(eval: Evaluator, tenure: usize, samples: usize) -> TabuSearch {
+        let n = eval.grid().n();
+        let score = eval.score();
+        TabuSearch {
+            best: eval.grid().clone(),
+            best_score: score,
+            eval,
+            score,
+            stats: MoveStats::default(),
+            iteration: 0,
+            tenure,
+            samples,
+            row_until: vec![0; n * n],
+            col_until: vec![0; n * n],
+            cell_until: vec![0; n * n * (DOT as usize + 1)],
+        }
+    }
+
+    /// Steps until `budget` is used up; a step counts as the `samples`
+    /// moves it tries.
+    pub fn run<R: Rng>(&mut self, moves: &[MoveKind], budget: Budget, rng: &mut R) {
+        let mut sampler = Sampler::new();
+        let from = Instant::now();
+        let first = self.iteration;
+        while budget
+            .progress(from, (self.iteration - first) * self.samples)
+            .is_some()
+        {
+            let now = Instant::now();
+            sampler.sample(now, "tabu", self.iteration, self.score, self.best_score);
+            self.step(moves, rng);
+        }
+    }
+
+    /// Tries `samples` random moves and applies the best one that is not
+    /// tabu, or a tabu one if it gives a new best (aspiration). Moves that
+    /// change no cell are skipped.
+    pub fn step<R: Rng>(&mut self, moves: &[MoveKind], rng: &mut R) {
+        self.iteration += 1;
+        let mut chosen: Option<(i64, Move, usize)> = None;
+        let mut kinds = Vec::with_capacity(self.samples);
+        // every move is tried on and undone from the same state
+        let mut output = None;
+        for _ in 0..self.samples {
+            let kind = *moves.choose(rng).unwrap();
+            let mv = match Move::random_cached(kind, &self.eval, &mut output, rng) {
+                Some(mv) => mv,
+                None => continue,
+            };
+            let mut undo = mv.apply(&mut self.eval);
+            // a move that writes nothing is never tabu and would stall the
+            // search at a local optimum
+            if undo.is_empty() {
+                continue;
+            }
+            let score = self.eval.score();
+            let allowed = !self.is_tabu(mv, &undo) || score > self.best_score;
+            self.eval.apply(&mut undo);
+            kinds.push(kind);
+            let better = match chosen {
+                Some((s, _, _)) => score > s,
+                None => true,
+            };
+            if allowed && better {
+                chosen = Some((score, mv, kinds.len() - 1));
+            }
+        }
+        for (k, &kind) in kinds.iter().enumerate() {
+            self.stats
+                .record(kind, matches!(chosen, Some((_, _, c)) if c == k));
+        }
+
+        let (score, mv, _) = match chosen {
+            Some(c) => c,
+            None => return,
+        };
+        let undo = mv.apply(&mut self.eval);
+        self.score = score;
+        if score > self.best_score {
+            self.best_score = score;
+            self.best = self.eval.grid().clone();
+        }
+        self.forbid_undo(mv, &undo);
+    }
+
+    /// Makes undoing `mv`, whose undo list is `undo`, tabu for the next
+    /// `tenure` steps.
+    fn forbid_undo(&mut self, mv: Move, undo: &[Write]) {
+        let until = self.iteration + self.tenure;
+        let n = self.eval.grid().n();
+        match mv {
+            Move::RotateRow(i, st) => self.row_until[i * n + (n - st % n) % n] = until,
+            Move::RotateCol(j, st) => self.col_until[j * n + (n - st % n) % n] = until,
+            _ => {
+                for &(i, j, old) in undo {
+                    let cell = self.cell(i, j, old);
+                    self.cell_until[cell] = until;
+                }
+            }
+        }
+    }
+
+    /// `undo` is the undo list of `mv`, applied to the current state.
+    fn is_tabu(&self, mv: Move, undo: &[Write]) -> bool {
+        let n = self.eval.grid().n();
+        match mv {
+            Move::RotateRow(i, st) => self.row_until[i * n + st % n] >= self.iteration,
+            Move::RotateCol(j, st) => self.col_until[j * n + st % n] >= self.iteration,
+            _ => undo.iter().any(|&(i, j, _)| {
+                self.cell_until[self.cell(i, j, self.eval.get(i, j))] >= self.iteration
+            }),
+        }
+    }
+
+    fn cell(&self, i: usize, j: usize, v: u8) -> usize {
+        let n = self.eval.grid().n();
+        (i * n + j) * (DOT as usize + 1) + v as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judge;
+
+    /// Whether `mv` is tabu at the current step, leaving the state unchanged.
+    fn is_tabu(tabu: &mut TabuSearch, mv: Move) -> bool {
+        let mut undo = mv.apply(&mut tabu.eval);
+        let is_tabu = tabu.is_tabu(mv, &undo);
+        tabu.eval.apply(&mut undo);
+        is_tabu
+    }
+
+    #[test]
+    fn undoing_a_move_is_tabu_for_tenure_steps() {
+        let tenure = 5;
+        let (input, answer) = judge::gen_with_answer(0);
+        let n = input.N;
+        let moves = [
+            (Move::RotateRow(3, 7), Move::RotateRow(3, n - 7)),
+            (Move::RotateCol(4, 2), Move::RotateCol(4, n - 2)),
+            (
+                Move::ChangeCell(1, 2, (answer.a[1][2] as u8 - b'A' + 1) % 8),
+                Move::ChangeCell(1, 2, answer.a[1][2] as u8 - b'A'),
+            ),
+        ];
+        for (mv, back) in moves {
+            let mut tabu = TabuSearch::new(Evaluator::new(&input, &answer.a), tenure, 1);
+            tabu.iteration += 1;
+            let undo = mv.apply(&mut tabu.eval);
+            tabu.forbid_undo(mv, &undo);
+            for step in 1..=tenure + 1 {
+                tabu.iteration += 1;
+                assert_eq!(is_tabu(&mut tabu, back), step <= tenure, "{:?}", back);
+                // repeating the move is not undoing it
+                assert!(!is_tabu(&mut tabu, mv), "{:?}", mv);
+            }
+        }
+    }
+}
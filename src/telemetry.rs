@@ -0,0 +1,195 @@
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Time between two score samples of a search.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Turns telemetry on for the whole process. While it is off (the default,
+/// and what submissions run with) nothing is printed; while it is on, every
+/// `Record::emit` prints one JSON line to stderr, e.g.
+/// `{"event":"phase","name":"sa","seconds":2.341}`.
+pub fn enable(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// One JSON object, printed as a line by `emit`.
+pub struct Record {
+    line: String,
+}
+
+pub fn record(event: &str) -> Record {
+    Record {
+        line: String::new(),
+    }
+    .str("event", event)
+}
+
+impl Record {
+    fn key(&mut self, key: &str) {
+        self.line.push(if self.line.is_empty() { '{' } else { ',' });
+        write_str(&mut self.line, key);
+        self.line.push(':');
+    }
+
+    pub fn int(mut self, key: &str, v: i64) -> Record {
+        self.key(key);
+        write!(self.line, "{}", v).unwrap();
+        self
+    }
+
+    pub fn float(mut self, key: &str, v: f64) -> Record {
+        self.key(key);
+        if v.is_finite() {
+            write!(self.line, "{}", v).unwrap();
+        } else {
+            self.line.push_str("null");
+        }
+        self
+    }
+
+    pub fn str(mut self, key: &str, v: &str) -> Record {
+        self.key(key);
+        write_str(&mut self.line, v);
+        self
+    }
+
+    pub fn bool(mut self, key: &str, v: bool) -> Record {
+        self.key(key);
+        self.line.push_str(if v { "true" } else { "false" });
+        self
+    }
+
+    pub fn null(mut self, key: &str) -> Record {
+        self.key(key);
+        self.line.push_str("null");
+        self
+    }
+
+    pub fn strs<'a, I: IntoIterator<Item = &'a str>>(mut self, key: &str, v: I) -> Record {
+        self.key(key);
+        self.line.push('[');
+        for (k, x) in v.into_iter().enumerate() {
+            if k > 0 {
+                self.line.push(',');
+            }
+            write_str(&mut self.line, x);
+        }
+        self.line.push(']');
+        self
+    }
+
+    pub fn ints<I: IntoIterator<Item = i64>>(mut self, key: &str, v: I) -> Record {
+        self.key(key);
+        self.line.push('[');
+        for (k, x) in v.into_iter().enumerate() {
+            if k > 0 {
+                self.line.push(',');
+            }
+            write!(self.line, "{}", x).unwrap();
+        }
+        self.line.push(']');
+        self
+    }
+
+    pub fn emit(mut self) {
+        if enabled() {
+            self.line.push('}');
+            eprintln!("{}", self.line);
+        }
+    }
+}
+
+fn write_str(line: &mut String, s: &str) {
+    line.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(line, "\\u{:04x}", c as u32).unwrap(),
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+/// Decides when a search records a `sample` of its score: every
+/// `SAMPLE_INTERVAL`, and never while telemetry is off.
+#[derive(Clone)]
+pub struct Sampler {
+    start: Instant,
+    next: Instant,
+    on: bool,
+}
+
+impl Sampler {
+    pub fn new() -> Sampler {
+        let start = Instant::now();
+        Sampler {
+            start,
+            next: start,
+            on: enabled(),
+        }
+    }
+
+    /// Emits a `sample` record if one is due at `now`.
+    pub fn sample(&mut self, now: Instant, search: &str, iteration: usize, score: i64, best: i64) {
+        if !self.on || now < self.next {
+            return;
+        }
+        self.next = now + SAMPLE_INTERVAL;
+        record("sample")
+            .str("search", search)
+            .float("seconds", (now - self.start).as_secs_f64())
+            .int("iteration", iteration as i64)
+            .int("score", score)
+            .int("best", best)
+            .emit();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads back a JSON string literal written by `write_str`.
+    fn read_str(json: &str) -> String {
+        let inner = &json[1..json.len() - 1];
+        let mut s = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            assert!(c != '"' && (c as u32) >= 0x20, "unescaped {:?}", c);
+            if c != '\\' {
+                s.push(c);
+                continue;
+            }
+            match chars.next().unwrap() {
+                '"' => s.push('"'),
+                '\\' => s.push('\\'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    s.push(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap());
+                }
+                c => panic!("unexpected escape {:?}", c),
+            }
+        }
+        s
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        for s in ["", "plain", "a \"quoted\" C:\\path\nnext\tline\u{1}", "é ü"] {
+            let mut json = String::new();
+            write_str(&mut json, s);
+            assert_eq!(read_str(&json), s);
+        }
+        let line = record("config").str("value", "say \"hi\"\\\n").line;
+        assert_eq!(line, r#"{"event":"config","value":"say \"hi\"\\\u000a""#);
+    }
+}
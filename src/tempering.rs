@@ -0,0 +1,161 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+
+use rand::prelude::*;
+
+use crate::annealing::Chain;
+use crate::config::{ParallelMode, SolverConfig};
+use crate::evaluator::Evaluator;
+use crate::grid::Grid;
+use crate::kick::Kicker;
+use crate::moves::MoveStats;
+use crate::schedule::Budget;
+use crate::telemetry;
+
+/// Steps each chain takes between two synchronizations when the run is
+/// limited by steps rather than by time.
+const EXCHANGE_STEPS: usize = 2000;
+
+/// Runs `config.chains` annealing chains on a thread each, starting from
+/// `eval`. Chain `k` keeps a fixed temperature on a geometric ladder from
+/// `end_temp` (coldest) to `start_temp` (hottest). Every `exchange_interval`
+/// (or `EXCHANGE_STEPS` steps, so that each chain takes the `budget` steps)
+/// the chains are synchronized: neighbours swap states with the usual replica
+/// exchange probability, or every chain adopts the best state found so far.
+/// A chain that finds no new best for `config.patience` steps is kicked like
+/// a single annealing run.
+pub fn run<R: Rng>(
+    eval: Evaluator,
+    config: &SolverConfig,
+    rng: &mut R,
+    budget: Budget,
+) -> (Grid, MoveStats) {
+    let k = config.chains;
+    let temps: Vec<f64> = (0..k)
+        .map(|i| {
+            let t = if k == 1 {
+                0.0
+            } else {
+                i as f64 / (k - 1) as f64
+            };
+            config.end_temp * (config.start_temp / config.end_temp).powf(t)
+        })
+        .collect();
+    // only chains with patience ever kick
+    let kicker = if config.patience > 0 {
+        Some(Kicker::new(&eval, config))
+    } else {
+        None
+    };
+    let mut chains: Vec<Chain> = (0..k)
+        .map(|_| Chain::new(eval.clone(), rng.gen()))
+        .collect();
+
+    thread::scope(|scope| {
+        // one worker per chain for the whole run; chains go to their worker
+        // for an epoch and come back to be synchronized
+        let (done_tx, done_rx) = mpsc::channel::<(usize, Chain)>();
+        let workers: Vec<mpsc::Sender<(Chain, Budget)>> = temps
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(i, temp)| {
+                let (tx, rx) = mpsc::channel::<(Chain, Budget)>();
+                let done_tx = done_tx.clone();
+                let kicker = kicker.as_ref();
+                scope.spawn(move || {
+                    for (mut chain, epoch) in rx {
+                        chain.anneal_with_kicks(
+                            &config.moves,
+                            epoch,
+                            |_| temp,
+                            config.patience,
+                            |eval, rng| {
+                                if let Some(kicker) = kicker {
+                                    kicker.kick(eval, rng);
+                                }
+                            },
+                        );
+                        done_tx.send((i, chain)).unwrap();
+                    }
+                });
+                tx
+            })
+            .collect();
+
+        let mut done = 0;
+        loop {
+            let epoch = match budget {
+                Budget::Until(until) => {
+                    let now = Instant::now();
+                    if now >= until {
+                        break;
+                    }
+                    Budget::Until((now + config.exchange_interval).min(until))
+                }
+                Budget::Steps(total) => {
+                    if done >= total {
+                        break;
+                    }
+                    let steps = EXCHANGE_STEPS.min(total - done);
+                    done += steps;
+                    Budget::Steps(steps)
+                }
+            };
+            for (worker, chain) in workers.iter().zip(chains.drain(..)) {
+                worker.send((chain, epoch)).unwrap();
+            }
+            let mut back: Vec<Option<Chain>> = (0..k).map(|_| None).collect();
+            for _ in 0..k {
+                let (i, chain) = done_rx.recv().unwrap();
+                back[i] = Some(chain);
+            }
+            chains = back.into_iter().map(|c| c.unwrap()).collect();
+
+            match config.parallel {
+                ParallelMode::Tempering => {
+                    for i in 0..k.saturating_sub(1) {
+                        let (cold, hot) = (chains[i].score as f64, chains[i + 1].score as f64);
+                        let prob = ((hot - cold) * (1.0 / temps[i] - 1.0 / temps[i + 1])).exp();
+                        if prob > rng.gen::<f64>() {
+                            let a = chains[i].eval.clone();
+                            let b = chains[i + 1].eval.clone();
+                            chains[i].reset(b);
+                            chains[i + 1].reset(a);
+                        }
+                    }
+                }
+                ParallelMode::AdoptBest => {
+                    let best = chains.iter().max_by_key(|c| c.best_score).unwrap();
+                    let best = Evaluator::from_grid(eval.dict.clone(), best.best.clone());
+                    for chain in chains.iter_mut() {
+                        chain.reset(best.clone());
+                    }
+                }
+            }
+        }
+        // closing the channels ends the workers
+        drop(workers);
+    });
+
+    let mut stats = MoveStats::default();
+    for chain in &chains {
+        stats.merge(&chain.stats);
+    }
+    let best = chains.iter().max_by_key(|c| c.best_score).unwrap();
+    telemetry::record("search")
+        .str("search", "sa")
+        .int("chains", k as i64)
+        .int(
+            "iterations",
+            chains.iter().map(|c| c.iteration).sum::<usize>() as i64,
+        )
+        .int(
+            "kicks",
+            chains.iter().map(|c| c.kicks).sum::<usize>() as i64,
+        )
+        .int("best", best.best_score)
+        .emit();
+    (best.best.clone(), stats)
+}
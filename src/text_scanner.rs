@@ -1,3 +1,11 @@
+// Vendored scanner, kept as is apart from what the compiler requires.
+#![allow(dead_code)]
+#![allow(
+    clippy::enum_variant_names,
+    clippy::unbuffered_bytes,
+    clippy::unwrap_or_default
+)]
+
 #[derive(Debug)]
 pub enum Error {
     IoError(std::io::Error),
@@ -87,7 +95,7 @@ pub fn fscanln<R: std::io::BufRead, T: FromTokens>(reader: &mut R) -> Result<T,
     FromTokens::from_tokens(&mut tokenizer)
 }
 
-pub fn fscan_iter<R: std::io::Read, T: FromTokens>(reader: &mut R) -> FscanIter<R, T> {
+pub fn fscan_iter<R: std::io::Read, T: FromTokens>(reader: &mut R) -> FscanIter<'_, R, T> {
     FscanIter {
         tokenizer: Tokenizer::new(reader),
         item_type: std::marker::PhantomData,
@@ -157,7 +165,7 @@ where
     item_type: std::marker::PhantomData<T>,
 }
 
-impl<'a, T: FromTokens> Iterator for ScanlnIter<T> {
+impl<T: FromTokens> Iterator for ScanlnIter<T> {
     type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -2,14 +2,70 @@ use std::io::prelude::*;
 use tools::*;
 
 fn main() {
-	if std::env::args().len() != 2 {
-		eprintln!("Usage: {} seeds.txt", std::env::args().nth(0).unwrap());
+	let mut args: Vec<String> = std::env::args().collect();
+	let mut flag = |name: &str| -> Option<String> {
+		match args.iter().position(|a| a == name) {
+			Some(i) if i + 1 < args.len() => {
+				let v = args.remove(i + 1);
+				args.remove(i);
+				Some(v)
+			}
+			_ => None,
+		}
+	};
+	let invalid = |name: &str, v: &str| -> ! {
+		eprintln!("invalid value for {}: {}", name, v);
+		std::process::exit(1)
+	};
+	let number = |name: &str, v: &str| -> usize { v.parse().unwrap_or_else(|_| invalid(name, v)) };
+	// "A" or "A-B"
+	let range = |name: &str, v: &str| -> std::ops::RangeInclusive<usize> {
+		match v.find('-') {
+			Some(i) => number(name, &v[..i])..=number(name, &v[i + 1..]),
+			None => number(name, v)..=number(name, v),
+		}
+	};
+	let mut config = GenConfig::default();
+	if let Some(v) = flag("--size") {
+		config.N = number("--size", &v);
+	}
+	if let Some(v) = flag("--letters") {
+		config.K = number("--letters", &v);
+	}
+	if let Some(v) = flag("--L") {
+		config.L = range("--L", &v);
+	}
+	if let Some(v) = flag("--M") {
+		config.M = range("--M", &v);
+	}
+	if let Some(v) = flag("--delta") {
+		config.delta = number("--delta", &v);
+	}
+	if let Some(v) = flag("--weights") {
+		config.weights = v.split(',').map(|w| w.parse().unwrap_or_else(|_| invalid("--weights", &v))).collect();
+	}
+	let answer = match args.iter().position(|a| a == "--answer") {
+		Some(i) => {
+			args.remove(i);
+			true
+		}
+		None => false,
+	};
+	if args.len() != 2 {
+		eprintln!("Usage: {} [--size N] [--letters K] [--L MIN[-MAX]] [--M MIN[-MAX]] [--delta D] [--weights W1,..,WK] [--answer] seeds.txt", args[0]);
 		return;
 	}
+	if let Err(reason) = config.check() {
+		eprintln!("{}", reason);
+		std::process::exit(1);
+	}
 	if !std::path::Path::new("in").exists() {
 		std::fs::create_dir("in").unwrap();
 	}
-	let f = std::env::args().nth(1).unwrap();
+	if answer && !std::path::Path::new("ans").exists() {
+		std::fs::create_dir("ans").unwrap();
+	}
+	let f = args[1].clone();
 	let f = std::fs::File::open(&f).unwrap_or_else(|_| { eprintln!("no such file: {}", f); std::process::exit(1) });
 	let f = std::io::BufReader::new(f);
 	let mut id = 0;
@@ -20,9 +76,13 @@ fn main() {
 			continue;
 		}
 		let seed = line.parse::<u64>().unwrap_or_else(|_| { eprintln!("parse failed: {}", line); std::process::exit(1) });
-		let input = gen(seed);
+		let (input, ans) = gen_with(seed, &config);
 		let mut w = std::io::BufWriter::new(std::fs::File::create(format!("in/{:04}.txt", id)).unwrap());
 		write!(w, "{}", input).unwrap();
+		if answer {
+			let mut w = std::io::BufWriter::new(std::fs::File::create(format!("ans/{:04}.txt", id)).unwrap());
+			write!(w, "{}", ans).unwrap();
+		}
 		id += 1;
 	}
 }
@@ -25,18 +25,28 @@ macro_rules! mat {
 	($e:expr; $d:expr $(; $ds:expr)+) => { Vec::from(vec![mat![$e $(; $ds)*]; $d]) };
 }
 
-pub const N: usize = 20;
+/// Grid size and alphabet size of the contest.
+pub const DEFAULT_N: usize = 20;
+pub const DEFAULT_K: usize = 8;
 
 pub type Output = Vec<Vec<char>>;
 
 pub struct Input {
+	pub N: usize,
+	/// letters 'A'.. 'A' + K - 1 are used, K <= 8
+	pub K: usize,
 	pub M: usize,
 	pub s: Vec<Vec<char>>,
 }
 
+/// The header is "N M" as in the contest, followed by K when it is not the default.
 impl std::fmt::Display for Input {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		writeln!(f, "{} {}", N, self.M)?;
+		if self.K == DEFAULT_K {
+			writeln!(f, "{} {}", self.N, self.M)?;
+		} else {
+			writeln!(f, "{} {} {}", self.N, self.M, self.K)?;
+		}
 		for i in 0..self.M {
 			writeln!(f, "{}", self.s[i].iter().collect::<String>())?;
 		}
@@ -45,39 +55,39 @@ impl std::fmt::Display for Input {
 }
 
 pub fn parse_input(f: &str) -> Input {
-	let mut f = proconio::source::once::OnceSource::from(f);
+	let f = f.trim_start();
+	let (header, body) = f.split_at(f.find('\n').unwrap_or(f.len()));
+	let header: Vec<usize> = header.split_whitespace().map(|v| v.parse().unwrap()).collect();
+	let (N, M) = (header[0], header[1]);
+	let K = header.get(2).copied().unwrap_or(DEFAULT_K);
+	let mut f = proconio::source::once::OnceSource::from(body);
 	input! {
 		from &mut f,
-		_: usize, M: usize,
 		s: [Chars; M]
 	}
-	Input { M, s }
+	Input { N, K, M, s }
 }
 
-pub fn parse_output(_input: &Input, f: &str) -> Output {
+pub fn parse_output(input: &Input, f: &str) -> Output {
 	let f = proconio::source::once::OnceSource::from(f);
 	input! {
 		from f,
-		out: [Chars; N]
+		out: [Chars; input.N]
 	}
 	out
 }
 
 pub const DIR: [(usize, usize); 2] = [(0, 1), (1, 0)];
 
-pub fn mod_n(i: usize) -> usize {
-	if i >= N {
-		i - N
-	} else {
-		i
-	}
+pub fn mod_n(i: usize, n: usize) -> usize {
+	i % n
 }
 
 pub fn is_substring(a: &Vec<Vec<char>>, b: &Vec<char>, i: usize, j: usize, d: usize) -> bool {
 	let (di, dj) = DIR[d];
 	for k in 0..b.len() {
-		let i = mod_n(i + di * k);
-		let j = mod_n(j + dj * k);
+		let i = mod_n(i + di * k, a.len());
+		let j = mod_n(j + dj * k, a.len());
 		if a[i][j] != b[k] {
 			return false;
 		}
@@ -89,29 +99,44 @@ pub fn get_substring(a: &Vec<Vec<char>>, i: usize, j: usize, d: usize, k: usize)
 	let (di, dj) = DIR[d];
 	let mut b = vec![];
 	for k in 0..k {
-		let i = mod_n(i + di * k);
-		let j = mod_n(j + dj * k);
+		let i = mod_n(i + di * k, a.len());
+		let j = mod_n(j + dj * k, a.len());
 		b.push(a[i][j]);
 	}
 	b
 }
 
-pub fn compute_score_detail(input: &Input, out: &Output) -> (i64, String) {
-	let mut c = 0;
+/// Checks the shape and the letters of `out`; returns the number of '.' or
+/// the reason it is illegal.
+fn count_dots(N: usize, K: usize, out: &Output) -> Result<usize, String> {
+	let last = (b'A' + K as u8 - 1) as char;
+	if out.len() != N {
+		return Err(format!("illegal number of rows: {}", out.len()));
+	}
 	let mut d = 0;
 	for i in 0..N {
 		if out[i].len() != N {
-			return (0, format!("illegal length: {}", out[i].len()));
+			return Err(format!("illegal length: {}", out[i].len()));
 		}
 		for j in 0..N {
-			if (out[i][j] < 'A' || 'H' < out[i][j]) && out[i][j] != '.' {
-				return (0, format!("illegal char: {}", out[i][j]));
+			if (out[i][j] < 'A' || last < out[i][j]) && out[i][j] != '.' {
+				return Err(format!("illegal char: {}", out[i][j]));
 			}
 			if out[i][j] == '.' {
 				d += 1;
 			}
 		}
 	}
+	Ok(d)
+}
+
+pub fn compute_score_detail(input: &Input, out: &Output) -> (i64, String) {
+	let N = input.N;
+	let mut c = 0;
+	let d = match count_dots(N, input.K, out) {
+		Ok(d) => d,
+		Err(reason) => return (0, reason),
+	};
 	for k in 0..input.M {
 		let mut used = false;
 		for i in 0..N {
@@ -135,25 +160,227 @@ pub fn compute_score_detail(input: &Input, out: &Output) -> (i64, String) {
 	(score.round() as i64, String::new())
 }
 
+/// Scores outputs with an Aho-Corasick automaton over all patterns of an
+/// `Input`. Build it once per input; `compute_score_detail` then streams the
+/// 2N cyclic lines of an output through the automaton and returns the same
+/// result as the free function `compute_score_detail`.
+pub struct Scorer {
+	N: usize,
+	K: usize,
+	M: usize,
+	max_len: usize,
+	next: Vec<[usize; 8]>,
+	/// distinct patterns ending at each node, following dictionary suffix links
+	out: Vec<Vec<usize>>,
+	/// number of copies of each distinct pattern in the input
+	count: Vec<usize>,
+}
+
+impl Scorer {
+	pub fn new(input: &Input) -> Scorer {
+		let mut next = vec![[0; 8]];
+		let mut out: Vec<Vec<usize>> = vec![vec![]];
+		let mut count = vec![];
+		let mut max_len = 0;
+		for s in &input.s {
+			max_len = max_len.max(s.len());
+			let mut v = 0;
+			for &c in s {
+				let c = (c as u8 - b'A') as usize;
+				if next[v][c] == 0 {
+					next[v][c] = next.len();
+					next.push([0; 8]);
+					out.push(vec![]);
+				}
+				v = next[v][c];
+			}
+			if out[v].is_empty() {
+				out[v].push(count.len());
+				count.push(0);
+			}
+			count[out[v][0]] += 1;
+		}
+		let mut fail = vec![0; next.len()];
+		let mut queue = std::collections::VecDeque::new();
+		for &u in &next[0] {
+			if u != 0 {
+				queue.push_back(u);
+			}
+		}
+		while let Some(v) = queue.pop_front() {
+			let f = fail[v];
+			let inherited = out[f].clone();
+			out[v].extend(inherited);
+			let fallback = next[f];
+			for (u, &w) in next[v].iter_mut().zip(&fallback) {
+				if *u != 0 {
+					fail[*u] = w;
+					queue.push_back(*u);
+				} else {
+					*u = w;
+				}
+			}
+		}
+		Scorer {
+			N: input.N,
+			K: input.K,
+			M: input.M,
+			max_len,
+			next,
+			out,
+			count,
+		}
+	}
+
+	pub fn compute_score_detail(&self, out: &Output) -> (i64, String) {
+		let N = self.N;
+		let d = match count_dots(N, self.K, out) {
+			Ok(d) => d,
+			Err(reason) => return (0, reason),
+		};
+		let mut used = vec![false; self.count.len()];
+		for &(di, dj) in &DIR {
+			for s in 0..N {
+				// each line is read N + max_len - 1 characters long so that
+				// occurrences across the seam are seen as well
+				let mut v = 0;
+				for k in 0..N + self.max_len.max(1) - 1 {
+					let i = (s * dj + k * di) % N;
+					let j = (s * di + k * dj) % N;
+					if out[i][j] == '.' {
+						v = 0;
+						continue;
+					}
+					v = self.next[v][(out[i][j] as u8 - b'A') as usize];
+					for &p in &self.out[v] {
+						used[p] = true;
+					}
+				}
+			}
+		}
+		let c: usize = (0..self.count.len())
+			.filter(|&p| used[p])
+			.map(|p| self.count[p])
+			.sum();
+		let score = if c < self.M {
+			1e8 * c as f64 / self.M as f64
+		} else {
+			1e8 * (2 * N * N) as f64 / (2 * N * N - d) as f64
+		};
+		(score.round() as i64, String::new())
+	}
+}
+
+/// Parameters of `gen_with`. `GenConfig::default()` is the distribution of the contest.
+pub struct GenConfig {
+	pub N: usize,
+	/// letters 'A'.. 'A' + K - 1 are used, K <= 8
+	pub K: usize,
+	/// range of the base length L
+	pub L: std::ops::RangeInclusive<usize>,
+	/// range of the number of patterns
+	pub M: std::ops::RangeInclusive<usize>,
+	/// pattern lengths are L - delta ..= L + delta
+	pub delta: usize,
+	/// relative frequency of each letter in the planted grid; uniform if empty
+	pub weights: Vec<f64>,
+}
+
+impl Default for GenConfig {
+	fn default() -> Self {
+		GenConfig { N: DEFAULT_N, K: DEFAULT_K, L: 4..=10, M: 400..=800, delta: 2, weights: vec![] }
+	}
+}
+
+impl GenConfig {
+	/// Returns the reason `gen_with` cannot use this config.
+	pub fn check(&self) -> Result<(), String> {
+		if self.N < 2 {
+			return Err(format!("illegal N: {}", self.N));
+		}
+		if !(1..=8).contains(&self.K) {
+			return Err(format!("illegal K: {}", self.K));
+		}
+		if self.L.is_empty() || *self.L.start() <= self.delta {
+			return Err(format!("illegal L: {:?} with delta {}", self.L, self.delta));
+		}
+		if self.M.is_empty() || *self.M.start() == 0 {
+			return Err(format!("illegal M: {:?}", self.M));
+		}
+		if !self.weights.is_empty() && (self.weights.len() != self.K || rand::distributions::WeightedIndex::new(&self.weights).is_err()) {
+			return Err(format!("illegal weights for K = {}: {:?}", self.K, self.weights));
+		}
+		Ok(())
+	}
+}
+
 pub fn gen(seed: u64) -> Input {
+	gen_with(seed, &GenConfig::default()).0
+}
+
+/// `gen` on an `N x N` grid with `K` letters; `gen_sized(seed, 20, 8)` is `gen(seed)`.
+pub fn gen_sized(seed: u64, N: usize, K: usize) -> Input {
+	gen_with(seed, &GenConfig { N, K, ..GenConfig::default() }).0
+}
+
+/// The grid `gen` cuts the patterns out of, and where it cuts each of them:
+/// `s[p]` is `get_substring(&a, i, j, d, s[p].len())` for `pos[p] = (i, j, d)`.
+/// `a` covers every pattern without a '.', so it scores 1e8.
+pub struct Answer {
+	pub a: Output,
+	pub pos: Vec<(usize, usize, usize)>,
+}
+
+/// The rows of `a`, which `parse_output` reads as an output, followed by one "i j d" line per pattern.
+impl std::fmt::Display for Answer {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		for row in &self.a {
+			writeln!(f, "{}", row.iter().collect::<String>())?;
+		}
+		for &(i, j, d) in &self.pos {
+			writeln!(f, "{} {} {}", i, j, d)?;
+		}
+		Ok(())
+	}
+}
+
+/// `gen` together with its planted `Answer`.
+pub fn gen_with_answer(seed: u64) -> (Input, Answer) {
+	gen_with(seed, &GenConfig::default())
+}
+
+/// Generates an input and its planted `Answer` from `config`, which must pass `GenConfig::check`.
+/// The values are drawn with the same types as the official generator, so the default config
+/// reproduces its inputs exactly.
+pub fn gen_with(seed: u64, config: &GenConfig) -> (Input, Answer) {
+	config.check().unwrap();
+	let (N, K) = (config.N, config.K);
 	let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(seed);
+	let letters = if config.weights.is_empty() { None } else { Some(rand::distributions::WeightedIndex::new(&config.weights).unwrap()) };
 	let mut a = mat!['.'; N; N];
 	for i in 0..N {
 		for j in 0..N {
-			a[i][j] = (b'A' + rng.gen_range(0, 8)) as char;
+			let c = match &letters {
+				Some(letters) => letters.sample(&mut rng) as u8,
+				None => rng.gen_range(0, K as u8),
+			};
+			a[i][j] = (b'A' + c) as char;
 		}
 	}
-	let L = rng.gen_range(4, 11);
-	let M = rng.gen_range(400, 801) as usize;
+	let L = rng.gen_range(*config.L.start() as i32, *config.L.end() as i32 + 1);
+	let M = rng.gen_range(*config.M.start() as i32, *config.M.end() as i32 + 1) as usize;
+	let delta = config.delta as i32;
 	let mut s = vec![];
+	let mut pos = vec![];
 	for _ in 0..M {
 		let i = rng.gen_range(0, N as u32) as usize;
 		let j = rng.gen_range(0, N as u32) as usize;
 		let d = rng.gen_range(0, 2) as usize;
-		let k = rng.gen_range(L - 2, L + 3) as usize;
+		let k = rng.gen_range(L - delta, L + delta + 1) as usize;
 		s.push(get_substring(&a, i, j, d, k));
+		pos.push((i, j, d));
 	}
-	Input { M, s }
+	(Input { N, K, M, s }, Answer { a, pos })
 }
 
 /// 0 <= val <= 1
@@ -179,7 +406,11 @@ pub fn vis_default(input: &Input, out: &Output) -> (i64, String, String) {
 }
 
 pub fn vis(input: &Input, out: &Output, show_i: i32) -> (i64, String, String) {
+	let N = input.N;
 	let (score, err) = compute_score_detail(input, out);
+	if out.len() != N || out.iter().any(|row| row.len() != N) {
+		return (score, String::new(), err);
+	}
 	let mut doc = svg::Document::new().set("viewBox", (0, 0, 30 * N, 30 * N)).set("width", 30 * N).set("height", 30 * N);
 	doc = doc.add(rect(0, 0, 30 * N, 30 * N, "white"));
 	for i in 0..=N {
@@ -200,8 +431,8 @@ pub fn vis(input: &Input, out: &Output, show_i: i32) -> (i64, String, String) {
 						if is_substring(&out, &input.s[k], i, j, d) {
 							let (di, dj) = DIR[d];
 							for p in 0..input.s[k].len() {
-								let i = mod_n(i + di * p);
-								let j = mod_n(j + dj * p);
+								let i = mod_n(i + di * p, N);
+								let j = mod_n(j + dj * p, N);
 								if d == 0 {
 									count_h[i][j] += 1;
 								} else {
@@ -237,3 +468,84 @@ pub fn vis(input: &Input, out: &Output, show_i: i32) -> (i64, String, String) {
 	}
 	(score, doc.to_string(), err)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_same(input: &Input, out: &Output) {
+		assert_eq!(Scorer::new(input).compute_score_detail(out), compute_score_detail(input, out));
+	}
+
+	#[test]
+	fn default_config_matches_the_contest_generator() {
+		for &(seed, header, first) in &[(0, "20 742", "AFHCGEH"), (1, "20 509", "FACH"), (2, "20 691", "EGEGFECEF")] {
+			let input = format!("{}", gen(seed));
+			let mut lines = input.lines();
+			assert_eq!(lines.next(), Some(header));
+			assert_eq!(lines.next(), Some(first));
+		}
+	}
+
+	#[test]
+	fn check_rejects_bad_configs() {
+		assert!(GenConfig::default().check().is_ok());
+		let bad = [
+			GenConfig { L: 2..=10, ..GenConfig::default() },
+			GenConfig { L: 5..=10, delta: 5, ..GenConfig::default() },
+			GenConfig { K: 0, ..GenConfig::default() },
+			GenConfig { K: 9, ..GenConfig::default() },
+			GenConfig { weights: vec![1.0; 7], ..GenConfig::default() },
+			GenConfig { K: 3, weights: vec![1.0, 2.0], ..GenConfig::default() },
+		];
+		for (i, config) in bad.iter().enumerate() {
+			assert!(config.check().is_err(), "config {} passed", i);
+		}
+		assert!(GenConfig { K: 3, weights: vec![1.0, 2.0, 3.0], ..GenConfig::default() }.check().is_ok());
+	}
+
+	#[test]
+	fn scorer_matches_compute_score_detail() {
+		let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0);
+		for &(N, K) in &[(20, 8), (6, 3), (4, 2)] {
+			for seed in 0..5 {
+				let (input, answer) = gen_with(seed, &GenConfig { N, K, ..GenConfig::default() });
+				assert_same(&input, &answer.a);
+				for &dots in &[0, 1, 5, 50] {
+					let mut out = answer.a.clone();
+					for i in 0..N {
+						for j in 0..N {
+							if rng.gen_range(0, 100) < dots {
+								out[i][j] = '.';
+							} else if rng.gen_range(0, 4) == 0 {
+								out[i][j] = (b'A' + rng.gen_range(0, K as u8)) as char;
+							}
+						}
+					}
+					assert_same(&input, &out);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn scorer_rejects_like_compute_score_detail() {
+		let (input, answer) = gen_with(0, &GenConfig { N: 6, K: 3, ..GenConfig::default() });
+		let mut short = answer.a.clone();
+		short.pop();
+		let mut long = answer.a.clone();
+		long.push(answer.a[0].clone());
+		let mut narrow = answer.a.clone();
+		narrow[2].pop();
+		let mut above_k = answer.a.clone();
+		above_k[1][4] = 'D';
+		let mut lower = answer.a.clone();
+		lower[0][0] = 'a';
+		for out in &[short, long, narrow, above_k, lower] {
+			let (score, reason) = compute_score_detail(&input, out);
+			assert_eq!(score, 0);
+			assert!(!reason.is_empty());
+			assert_same(&input, out);
+		}
+	}
+}